@@ -0,0 +1,1045 @@
+use anyhow::{bail, Context, Result};
+use glam::UVec2;
+use speedy2d::color::Color;
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::font::Font;
+use crate::grid::{Attributes, Grid, Tile, MAX_CELLS};
+use crate::palette::{self, Palette, ANSI_TO_VGA, VGA16};
+
+const SAUCE_SIZE: usize = 128;
+const COMMENT_SIZE: usize = 64;
+const EOF: u8 = 0x1A;
+const ESC: u8 = 0x1B;
+const DEFAULT_ANSI_WIDTH: u32 = 80;
+const DEFAULT_BIN_WIDTH: u32 = 160;
+
+const DATA_TYPE_CHARACTER: u8 = 1;
+const DATA_TYPE_BINARY_TEXT: u8 = 5;
+const DATA_TYPE_XBIN: u8 = 6;
+const FILE_TYPE_ANSI: u8 = 1;
+const FLAG_ICE_COLORS: u8 = 1;
+
+const XBIN_ID: &[u8; 5] = b"XBIN\x1A";
+const XBIN_PALETTE: u8 = 1;
+const XBIN_FONT: u8 = 1 << 1;
+const XBIN_COMPRESS: u8 = 1 << 2;
+const XBIN_NON_BLINK: u8 = 1 << 3;
+const XBIN_512_CHARS: u8 = 1 << 4;
+// Font heights XBin allows.
+const XBIN_FONT_HEIGHTS: std::ops::RangeInclusive<u32> = 1..=32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Ansi,
+    Bin,
+    XBin,
+}
+
+impl Kind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ans" | "asc" | "diz" | "nfo" => Some(Self::Ansi),
+            "bin" => Some(Self::Bin),
+            "xb" | "xbin" => Some(Self::XBin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sauce {
+    pub title: String,
+    pub author: String,
+    pub group: String,
+    pub date: String,
+    pub data_type: u8,
+    pub file_type: u8,
+    pub info: [u16; 4],
+    pub flags: u8,
+    pub font: String,
+    pub comments: Vec<String>,
+}
+
+impl Sauce {
+    pub const fn ice_colors(&self) -> bool {
+        self.flags & FLAG_ICE_COLORS != 0
+    }
+
+    fn parse(record: &[u8], comments: &[u8]) -> Self {
+        let text = |range: std::ops::Range<usize>| {
+            record[range]
+                .iter()
+                .map(|byte| char::from(*byte))
+                .collect::<String>()
+                .trim_end_matches([' ', '\0'])
+                .to_string()
+        };
+        let u16_at = |at: usize| u16::from_le_bytes([record[at], record[at + 1]]);
+        Self {
+            title: text(7..42),
+            author: text(42..62),
+            group: text(62..82),
+            date: text(82..90),
+            data_type: record[94],
+            file_type: record[95],
+            info: [u16_at(96), u16_at(98), u16_at(100), u16_at(102)],
+            flags: record[105],
+            font: text(106..128),
+            comments: comments
+                .chunks(COMMENT_SIZE)
+                .map(|line| {
+                    line.iter()
+                        .map(|byte| char::from(*byte))
+                        .collect::<String>()
+                        .trim_end_matches([' ', '\0'])
+                        .to_string()
+                })
+                .collect(),
+        }
+    }
+
+    fn to_bytes(&self, file_size: u32) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SAUCE_SIZE + 5 + self.comments.len() * COMMENT_SIZE);
+        bytes.push(EOF);
+        if !self.comments.is_empty() {
+            bytes.extend_from_slice(b"COMNT");
+            for comment in &self.comments {
+                bytes.extend(padded(comment, COMMENT_SIZE, b' '));
+            }
+        }
+        bytes.extend_from_slice(b"SAUCE00");
+        bytes.extend(padded(&self.title, 35, b' '));
+        bytes.extend(padded(&self.author, 20, b' '));
+        bytes.extend(padded(&self.group, 20, b' '));
+        let date = if self.date.is_empty() {
+            today()
+        } else {
+            self.date.clone()
+        };
+        bytes.extend(padded(&date, 8, b' '));
+        bytes.extend_from_slice(&file_size.to_le_bytes());
+        bytes.push(self.data_type);
+        bytes.push(self.file_type);
+        for info in self.info {
+            bytes.extend_from_slice(&info.to_le_bytes());
+        }
+        bytes.push(u8::try_from(self.comments.len()).unwrap_or(u8::MAX));
+        bytes.push(self.flags);
+        bytes.extend(padded(&self.font, 22, 0));
+        bytes
+    }
+}
+
+pub struct Imported {
+    pub grid: Grid,
+    pub sauce: Option<Sauce>,
+    pub font: Option<Font>,
+}
+
+pub fn import(bytes: &[u8], kind: Kind) -> Result<Imported> {
+    let (data, sauce) = split_sauce(bytes);
+    let ice_colors = sauce.as_ref().is_some_and(Sauce::ice_colors);
+    let (grid, font) = match kind {
+        Kind::Ansi => {
+            let width = sauce
+                .as_ref()
+                .filter(|sauce| sauce.data_type == DATA_TYPE_CHARACTER && sauce.info[0] > 0)
+                .map_or(DEFAULT_ANSI_WIDTH, |sauce| u32::from(sauce.info[0]));
+            (parse_ansi(data, width, ice_colors)?, None)
+        }
+        Kind::Bin => {
+            let width = sauce
+                .as_ref()
+                .filter(|sauce| sauce.data_type == DATA_TYPE_BINARY_TEXT && sauce.file_type > 0)
+                .map_or(DEFAULT_BIN_WIDTH, |sauce| u32::from(sauce.file_type) * 2);
+            (parse_bin(data, width, ice_colors)?, None)
+        }
+        Kind::XBin => parse_xbin(data)?,
+    };
+    Ok(Imported { grid, sauce, font })
+}
+
+pub fn export(grid: &Grid, kind: Kind, sauce: Option<&Sauce>, font: Option<&Font>) -> Vec<u8> {
+    let mut sauce = sauce.cloned().unwrap_or_default();
+    let width = u16::try_from(grid.width()).unwrap_or(u16::MAX);
+    let height = u16::try_from(grid.height()).unwrap_or(u16::MAX);
+    let ice_colors = grid
+        .tiles()
+        .iter()
+        .any(|tile| vga_index(tile.bg_index, tile.bg) >= 8);
+    sauce.flags = if ice_colors {
+        sauce.flags | FLAG_ICE_COLORS
+    } else {
+        sauce.flags & !FLAG_ICE_COLORS
+    };
+    if sauce.font.is_empty() {
+        sauce.font = "IBM VGA".to_string();
+    }
+
+    let mut bytes = match kind {
+        Kind::Ansi => {
+            sauce.data_type = DATA_TYPE_CHARACTER;
+            sauce.file_type = FILE_TYPE_ANSI;
+            sauce.info = [width, height, 0, 0];
+            write_ansi(grid, ice_colors)
+        }
+        Kind::Bin => {
+            sauce.data_type = DATA_TYPE_BINARY_TEXT;
+            sauce.file_type = u8::try_from(grid.width().div_ceil(2)).unwrap_or(u8::MAX);
+            sauce.info = [0; 4];
+            write_bin(grid, ice_colors)
+        }
+        Kind::XBin => {
+            sauce.data_type = DATA_TYPE_XBIN;
+            sauce.file_type = 0;
+            sauce.info = [0; 4];
+            write_xbin(grid, ice_colors, font)
+        }
+    };
+    let file_size = u32::try_from(bytes.len()).unwrap_or(u32::MAX);
+    bytes.extend(sauce.to_bytes(file_size));
+    bytes
+}
+
+fn split_sauce(bytes: &[u8]) -> (&[u8], Option<Sauce>) {
+    if bytes.len() < SAUCE_SIZE || !bytes[bytes.len() - SAUCE_SIZE..].starts_with(b"SAUCE") {
+        return (bytes, None);
+    }
+    let record = &bytes[bytes.len() - SAUCE_SIZE..];
+    let mut data = &bytes[..bytes.len() - SAUCE_SIZE];
+    let comments_size = 5 + usize::from(record[104]) * COMMENT_SIZE;
+    let mut comments: &[u8] = &[];
+    if record[104] > 0 && data.len() >= comments_size {
+        let block = &data[data.len() - comments_size..];
+        if block.starts_with(b"COMNT") {
+            comments = &block[5..];
+            data = &data[..data.len() - comments_size];
+        }
+    }
+    if data.last() == Some(&EOF) {
+        data = &data[..data.len() - 1];
+    }
+    (data, Some(Sauce::parse(record, comments)))
+}
+
+pub struct Terminal {
+    grid: Grid,
+    // A fixed size screen scrolls instead of growing.
+    fixed: bool,
+    rows_used: u32,
+    // Set when a growing screen would pass `MAX_CELLS`, the rest of the input is ignored.
+    too_big: bool,
+    cursor: UVec2,
+    saved: UVec2,
+    pending_wrap: bool,
+    ice_colors: bool,
+
+    fg: usize,
+    bg: usize,
+    fg_rgb: Option<Color>,
+    bg_rgb: Option<Color>,
+    bold: bool,
+    blink: bool,
+    attributes: Attributes,
+    // Colours for indexed cells and the blanks left by clears, resizes and scrolling.
+    palette: Palette,
+}
+
+impl Terminal {
+    fn new(width: u32, ice_colors: bool) -> Self {
+        Self {
+            grid: Grid::new(width.max(1), 25),
+            fixed: false,
+            rows_used: 0,
+            too_big: false,
+            cursor: UVec2::ZERO,
+            saved: UVec2::ZERO,
+            pending_wrap: false,
+            ice_colors,
+
+            fg: 7,
+            bg: 0,
+            fg_rgb: None,
+            bg_rgb: None,
+            bold: false,
+            blink: false,
+            attributes: Attributes::NONE,
+            palette: Palette::default(),
+        }
+    }
+
+    pub fn fixed(width: u32, height: u32) -> Self {
+        Self {
+            grid: Grid::new(width.max(1), height.max(1)),
+            fixed: true,
+            ..Self::new(width, false)
+        }
+    }
+
+    pub const fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    // For writing cells, the size only changes through `resize`.
+    pub const fn grid_mut(&mut self) -> &mut Grid {
+        &mut self.grid
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.grid.resize(width.max(1), height.max(1), &self.palette);
+        self.cursor = self.cursor.min(self.grid.size() - UVec2::ONE);
+        self.saved = self.saved.min(self.grid.size() - UVec2::ONE);
+    }
+
+    pub fn recolor(&mut self, palette: &Palette) {
+        self.palette = palette.clone();
+        self.grid.recolor(palette);
+    }
+
+    fn finish(mut self) -> Grid {
+        let width = self.grid.width();
+        self.grid
+            .resize(width, self.rows_used.max(1), &self.palette);
+        self.grid
+    }
+
+    // Characters are glyph indices, control characters and escape sequences drive the terminal.
+    pub fn feed(&mut self, chars: impl Iterator<Item = char>) {
+        let mut chars = chars.peekable();
+        while let Some(ch) = chars.next() {
+            if self.too_big {
+                break;
+            }
+            match ch {
+                ch if ch == char::from(EOF) => break,
+                ch if ch == char::from(ESC) && chars.peek() == Some(&'[') => {
+                    chars.next();
+                    let mut params = Vec::new();
+                    let mut current: Option<i64> = None;
+                    let mut final_byte = None;
+                    for ch in chars.by_ref() {
+                        match ch {
+                            '0'..='9' => {
+                                let digit = i64::from(ch.to_digit(10).unwrap_or(0));
+                                current = Some(
+                                    current
+                                        .unwrap_or(0)
+                                        .saturating_mul(10)
+                                        .saturating_add(digit),
+                                );
+                            }
+                            ';' => params.push(current.take().unwrap_or(0)),
+                            '\x40'..='\x7E' => {
+                                final_byte = Some(ch as u8);
+                                break;
+                            }
+                            _ => (),
+                        }
+                    }
+                    params.extend(current);
+                    if let Some(final_byte) = final_byte {
+                        self.csi(&params, final_byte);
+                    }
+                }
+                // IND, a line feed that keeps the column.
+                ch if ch == char::from(ESC) && chars.peek() == Some(&'D') => {
+                    chars.next();
+                    self.line_feed(self.cursor.x);
+                }
+                '\r' => self.move_to(0, i64::from(self.cursor.y)),
+                '\n' => self.line_feed(0),
+                '\t' => {
+                    let x = i64::from(self.cursor.x / 8 + 1) * 8;
+                    self.move_to(x, i64::from(self.cursor.y));
+                }
+                ch => self.put(ch),
+            }
+        }
+    }
+
+    fn brush(&self, ch: char) -> Tile {
+        let bright_fg = if self.bold && self.fg < 8 { 8 } else { 0 };
+        let bright_bg = if self.blink && self.ice_colors && self.bg < 8 {
+            8
+        } else {
+            0
+        };
+        let mut attributes = self.attributes;
+        // Bold that only brightens the colour isn't kept, so exports read back the same.
+        if self.bold && (self.fg >= 8 || self.fg_rgb.is_some()) {
+            attributes = attributes.with(Attributes::BOLD);
+        }
+        if self.blink && !self.ice_colors {
+            attributes = attributes.with(Attributes::BLINK);
+        }
+        let fg = (self.fg + bright_fg) as u8;
+        let bg = (self.bg + bright_bg) as u8;
+        let tile = Tile::new(ch).with_attributes(attributes);
+        let tile = self.fg_rgb.map_or_else(
+            || tile.with_fg_index(fg, &self.palette),
+            |color| tile.with_fg(color),
+        );
+        self.bg_rgb.map_or_else(
+            || tile.with_bg_index(bg, &self.palette),
+            |color| tile.with_bg(color),
+        )
+    }
+
+    // Fixed screens keep the cursor on them, `line_feed` scrolls them.
+    fn touch_row(&mut self, y: u32) {
+        if self.fixed {
+            return;
+        }
+        let width = self.grid.width();
+        let max_rows = MAX_CELLS / width;
+        if y >= max_rows {
+            self.too_big = true;
+            return;
+        }
+        if y >= self.grid.height() {
+            let rows = (y + 1).max(self.grid.height() * 2).min(max_rows);
+            self.grid.resize(width, rows, &self.palette);
+        }
+        self.rows_used = self.rows_used.max(y + 1);
+    }
+
+    fn scroll(&mut self, lines: u32) {
+        let (width, height) = (self.grid.width(), self.grid.height());
+        let blank = Tile::blank(&self.palette);
+        for y in 0..height {
+            for x in 0..width {
+                let below = self
+                    .grid
+                    .get(UVec2::new(x, y + lines))
+                    .copied()
+                    .unwrap_or(blank);
+                self.grid.set(UVec2::new(x, y), below);
+            }
+        }
+    }
+
+    // Moves to the next row at column `x`, scrolling a fixed screen at its bottom row.
+    fn line_feed(&mut self, x: u32) {
+        let y = self.cursor.y + 1;
+        if self.fixed && y >= self.grid.height() {
+            self.scroll(1);
+            self.move_to(i64::from(x), i64::from(y - 1));
+        } else {
+            self.move_to(i64::from(x), i64::from(y));
+        }
+    }
+
+    fn put(&mut self, ch: char) {
+        if self.pending_wrap {
+            self.line_feed(0);
+        }
+        self.touch_row(self.cursor.y);
+        self.grid.set(self.cursor, self.brush(ch));
+        if self.cursor.x + 1 >= self.grid.width() {
+            self.pending_wrap = true;
+        } else {
+            self.cursor.x += 1;
+        }
+    }
+
+    fn move_to(&mut self, x: i64, y: i64) {
+        let max_x = i64::from(self.grid.width() - 1);
+        let max_y = if self.fixed {
+            i64::from(self.grid.height() - 1)
+        } else {
+            i64::from(u16::MAX)
+        };
+        self.cursor = UVec2::new(x.clamp(0, max_x) as u32, y.clamp(0, max_y) as u32);
+        self.pending_wrap = false;
+    }
+
+    fn csi(&mut self, params: &[i64], final_byte: u8) {
+        let first = params.first().copied().filter(|n| *n > 0).unwrap_or(1);
+        let UVec2 { x, y } = self.cursor;
+        let (x, y) = (i64::from(x), i64::from(y));
+        match final_byte {
+            b'A' => self.move_to(x, y - first),
+            b'B' => self.move_to(x, y + first),
+            b'C' => self.move_to(x + first, y),
+            b'D' => self.move_to(x - first, y),
+            b'H' | b'f' => {
+                let column = params.get(1).copied().filter(|n| *n > 0).unwrap_or(1);
+                self.move_to(column - 1, first - 1);
+            }
+            b'J' if params.first() == Some(&2) => {
+                self.grid.clear(&self.palette);
+                self.move_to(0, 0);
+            }
+            b'K' => {
+                self.touch_row(self.cursor.y);
+                let blank = self.brush(' ');
+                let (from, to) = match params.first() {
+                    Some(1) => (0, self.cursor.x + 1),
+                    Some(2) => (0, self.grid.width()),
+                    _ => (self.cursor.x, self.grid.width()),
+                };
+                for x in from..to {
+                    self.grid.set(UVec2::new(x, self.cursor.y), blank);
+                }
+            }
+            b's' => self.saved = self.cursor,
+            b'u' => self.cursor = self.saved,
+            b'm' => self.sgr(params),
+            // PabloDraw 24 bit colour: ESC[0;r;g;bt for background, ESC[1;r;g;bt for foreground.
+            b't' if params.len() == 4 => {
+                let channel = |n: i64| n.clamp(0, 255) as u8;
+                let color =
+                    Color::from_int_rgb(channel(params[1]), channel(params[2]), channel(params[3]));
+                if params[0] == 0 {
+                    self.bg_rgb = Some(color);
+                } else {
+                    self.fg_rgb = Some(color);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn sgr(&mut self, params: &[i64]) {
+        if params.is_empty() {
+            self.sgr(&[0]);
+            return;
+        }
+        let mut params = params.iter().copied();
+        while let Some(param) = params.next() {
+            match param {
+                0 => {
+                    self.fg = 7;
+                    self.bg = 0;
+                    self.fg_rgb = None;
+                    self.bg_rgb = None;
+                    self.bold = false;
+                    self.blink = false;
+                    self.attributes = Attributes::NONE;
+                }
+                1 => self.bold = true,
+                22 => self.bold = false,
+                4 => self.attributes = self.attributes.with(Attributes::UNDERLINE),
+                24 => self.attributes = self.attributes.without(Attributes::UNDERLINE),
+                5 | 6 => self.blink = true,
+                25 => self.blink = false,
+                7 => self.attributes = self.attributes.with(Attributes::REVERSE),
+                27 => self.attributes = self.attributes.without(Attributes::REVERSE),
+                30..=37 => {
+                    self.fg = ANSI_TO_VGA[(param - 30) as usize];
+                    self.fg_rgb = None;
+                }
+                39 => {
+                    self.fg = 7;
+                    self.fg_rgb = None;
+                }
+                40..=47 => {
+                    self.bg = ANSI_TO_VGA[(param - 40) as usize];
+                    self.bg_rgb = None;
+                }
+                49 => {
+                    self.bg = 0;
+                    self.bg_rgb = None;
+                }
+                90..=97 => {
+                    self.fg = ANSI_TO_VGA[(param - 90) as usize] + 8;
+                    self.fg_rgb = None;
+                }
+                100..=107 => {
+                    self.bg = ANSI_TO_VGA[(param - 100) as usize] + 8;
+                    self.bg_rgb = None;
+                }
+                38 | 48 => {
+                    let color = match params.next() {
+                        Some(5) => params.next().map(|n| palette::xterm(n.clamp(0, 255) as u8)),
+                        Some(2) => {
+                            let mut channel = || params.next().unwrap_or(0).clamp(0, 255) as u8;
+                            Some(Color::from_int_rgb(channel(), channel(), channel()))
+                        }
+                        _ => None,
+                    };
+                    if param == 38 {
+                        self.fg_rgb = color;
+                    } else {
+                        self.bg_rgb = color;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+fn parse_ansi(data: &[u8], width: u32, ice_colors: bool) -> Result<Grid> {
+    let mut terminal = Terminal::new(width, ice_colors);
+    terminal.feed(data.iter().map(|byte| char::from(*byte)));
+    if terminal.too_big {
+        bail!("ANSI image is more than {MAX_CELLS} cells at {width} columns");
+    }
+    Ok(terminal.finish())
+}
+
+// Without a custom palette the tile refers to the active palette by index.
+fn tile_from_attribute(
+    ch: u8,
+    attribute: u8,
+    ice_colors: bool,
+    custom: Option<&[Color; 16]>,
+) -> Tile {
+    let fg = usize::from(attribute & 0x0F);
+    let blink = attribute & 0x80 != 0;
+    let bg = usize::from((attribute >> 4) & if ice_colors { 0x0F } else { 0x07 });
+    let attributes = if blink && !ice_colors {
+        Attributes::BLINK
+    } else {
+        Attributes::NONE
+    };
+    let tile = Tile::new(char::from(ch)).with_attributes(attributes);
+    match custom {
+        Some(colors) => tile.with_fg(colors[fg]).with_bg(colors[bg]),
+        None => Tile {
+            fg_index: Some(fg as u8),
+            bg_index: Some(bg as u8),
+            ..tile.with_fg(palette::vga(fg)).with_bg(palette::vga(bg))
+        },
+    }
+}
+
+fn attribute_from_tile(tile: &Tile, ice_colors: bool) -> u8 {
+    // Indexed colours keep their slot even when the active palette isn't VGA.
+    let fg = vga_index(tile.fg_index, tile.fg);
+    let bg = vga_index(tile.bg_index, tile.bg);
+    let blink = if !ice_colors && tile.attributes.contains(Attributes::BLINK) {
+        0x80
+    } else {
+        0
+    };
+    let bg = if ice_colors { bg } else { bg & 0x07 };
+    fg | (bg << 4) | blink
+}
+
+fn vga_index(index: Option<u8>, color: Color) -> u8 {
+    index
+        .filter(|idx| *idx < 16)
+        .unwrap_or_else(|| palette::nearest(&VGA16, color) as u8)
+}
+
+fn glyph_byte(ch: char) -> u8 {
+    u8::try_from(u32::from(ch)).unwrap_or(b'?')
+}
+
+fn vga_colors() -> [Color; 16] {
+    std::array::from_fn(palette::vga)
+}
+
+fn parse_bin(data: &[u8], width: u32, ice_colors: bool) -> Result<Grid> {
+    let width = width.max(1);
+    let cells = u32::try_from(data.len() / 2).unwrap_or(u32::MAX);
+    let height = cells.div_ceil(width).max(1);
+    if width.saturating_mul(height) > MAX_CELLS {
+        bail!("BIN image is {width}x{height}, too big");
+    }
+    let mut grid = Grid::new(width, height);
+    for (idx, pair) in data.chunks_exact(2).enumerate() {
+        let idx = idx as u32;
+        let tile = tile_from_attribute(pair[0], pair[1], ice_colors, None);
+        grid.set(UVec2::new(idx % width, idx / width), tile);
+    }
+    Ok(grid)
+}
+
+fn parse_xbin(data: &[u8]) -> Result<(Grid, Option<Font>)> {
+    if !data.starts_with(XBIN_ID) || data.len() < 11 {
+        bail!("Not an XBin file");
+    }
+    let width = u32::from(u16::from_le_bytes([data[5], data[6]]));
+    let height = u32::from(u16::from_le_bytes([data[7], data[8]]));
+    let font_height = u32::from(data[9]);
+    let flags = data[10];
+    let mut rest = &data[11..];
+    if width * height > MAX_CELLS {
+        bail!("XBin image is {width}x{height}, too big");
+    }
+
+    let mut colors = vga_colors();
+    let custom_palette = flags & XBIN_PALETTE != 0;
+    if custom_palette {
+        let palette = rest.get(..48).context("XBin palette is truncated")?;
+        for (color, rgb) in colors.iter_mut().zip(palette.chunks_exact(3)) {
+            let [r, g, b] = [rgb[0], rgb[1], rgb[2]].map(|v| (v & 0x3F) << 2 | (v & 0x3F) >> 4);
+            *color = Color::from_int_rgb(r, g, b);
+        }
+        rest = &rest[48..];
+    }
+
+    let mut font = None;
+    if flags & XBIN_FONT != 0 {
+        if !XBIN_FONT_HEIGHTS.contains(&font_height) {
+            bail!("XBin font height {font_height} isn't between 1 and 32");
+        }
+        let glyphs = if flags & XBIN_512_CHARS != 0 {
+            512
+        } else {
+            256
+        };
+        let size = (glyphs * font_height) as usize;
+        let lines = rest.get(..size).context("XBin font is truncated")?;
+        font = Some(Font {
+            height: font_height,
+            lines: lines.iter().map(|line| line.reverse_bits()).collect(),
+        });
+        rest = &rest[size..];
+    }
+
+    let cells = (width * height) as usize;
+    let pairs = if flags & XBIN_COMPRESS != 0 {
+        decompress_xbin(rest, cells)?
+    } else {
+        rest.get(..cells * 2)
+            .context("XBin image data is truncated")?
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect()
+    };
+    let ice_colors = flags & XBIN_NON_BLINK != 0;
+    let mut grid = Grid::new(width.max(1), height.max(1));
+    for (idx, (ch, attribute)) in pairs.into_iter().enumerate() {
+        let idx = idx as u32;
+        let tile =
+            tile_from_attribute(ch, attribute, ice_colors, custom_palette.then_some(&colors));
+        grid.set(UVec2::new(idx % width, idx / width), tile);
+    }
+    Ok((grid, font))
+}
+
+fn decompress_xbin(mut data: &[u8], cells: usize) -> Result<Vec<(u8, u8)>> {
+    let mut pairs = Vec::with_capacity(cells);
+    let mut next = |data: &mut &[u8]| -> Result<u8> {
+        let (byte, rest) = data.split_first().context("XBin image data is truncated")?;
+        *data = rest;
+        Ok(*byte)
+    };
+    while pairs.len() < cells {
+        let header = next(&mut data)?;
+        let count = usize::from(header & 0x3F) + 1;
+        match header >> 6 {
+            0 => {
+                for _ in 0..count {
+                    pairs.push((next(&mut data)?, next(&mut data)?));
+                }
+            }
+            1 => {
+                let ch = next(&mut data)?;
+                for _ in 0..count {
+                    pairs.push((ch, next(&mut data)?));
+                }
+            }
+            2 => {
+                let attribute = next(&mut data)?;
+                for _ in 0..count {
+                    pairs.push((next(&mut data)?, attribute));
+                }
+            }
+            _ => {
+                let pair = (next(&mut data)?, next(&mut data)?);
+                pairs.extend(std::iter::repeat_n(pair, count));
+            }
+        }
+    }
+    pairs.truncate(cells);
+    Ok(pairs)
+}
+
+fn write_ansi(grid: &Grid, ice_colors: bool) -> Vec<u8> {
+    let mut bytes = b"\x1b[0m".to_vec();
+    for row in grid.rows() {
+        let used = row
+            .iter()
+            .rposition(|tile| !tile.is_blank())
+            .map_or(0, |idx| idx + 1);
+        let mut current = None;
+        for tile in &row[..used] {
+            let fg = usize::from(vga_index(tile.fg_index, tile.fg));
+            let bg = usize::from(vga_index(tile.bg_index, tile.bg));
+            let style = (fg, bg, tile.attributes);
+            if current != Some(style) {
+                let mut params = vec!["0".to_string()];
+                if fg >= 8 || tile.attributes.contains(Attributes::BOLD) {
+                    params.push("1".to_string());
+                }
+                if bg >= 8 || (!ice_colors && tile.attributes.contains(Attributes::BLINK)) {
+                    params.push("5".to_string());
+                }
+                if tile.attributes.contains(Attributes::UNDERLINE) {
+                    params.push("4".to_string());
+                }
+                if tile.attributes.contains(Attributes::REVERSE) {
+                    params.push("7".to_string());
+                }
+                params.push(format!("3{}", vga_to_ansi(fg)));
+                params.push(format!("4{}", vga_to_ansi(bg)));
+                bytes.extend(format!("\x1b[{}m", params.join(";")).bytes());
+                current = Some(style);
+            }
+            // These bytes are interpreted by viewers instead of being shown as glyphs.
+            bytes.push(match glyph_byte(tile.ch) {
+                b'\r' | b'\n' | b'\t' | EOF | ESC => b' ',
+                byte => byte,
+            });
+        }
+        bytes.extend_from_slice(b"\x1b[0m\r\n");
+    }
+    bytes
+}
+
+fn vga_to_ansi(idx: usize) -> usize {
+    ANSI_TO_VGA
+        .iter()
+        .position(|vga| *vga == idx % 8)
+        .unwrap_or(7)
+}
+
+fn write_bin(grid: &Grid, ice_colors: bool) -> Vec<u8> {
+    let width = grid.width() + grid.width() % 2;
+    let mut bytes = Vec::with_capacity((width * grid.height() * 2) as usize);
+    for row in grid.rows() {
+        for tile in row {
+            bytes.push(glyph_byte(tile.ch));
+            bytes.push(attribute_from_tile(tile, ice_colors));
+        }
+        if row.len() as u32 != width {
+            bytes.extend_from_slice(&[b' ', 0x07]);
+        }
+    }
+    bytes
+}
+
+fn write_xbin(grid: &Grid, ice_colors: bool, font: Option<&Font>) -> Vec<u8> {
+    let font = font.filter(|font| font.glyph_count() == 256 || font.glyph_count() == 512);
+    let mut flags = XBIN_PALETTE;
+    if ice_colors {
+        flags |= XBIN_NON_BLINK;
+    }
+    let font_height = font.map_or(16, |font| font.height);
+    if let Some(font) = font {
+        flags |= XBIN_FONT;
+        if font.glyph_count() == 512 {
+            flags |= XBIN_512_CHARS;
+        }
+    }
+
+    let mut bytes = XBIN_ID.to_vec();
+    bytes.extend_from_slice(
+        &u16::try_from(grid.width())
+            .unwrap_or(u16::MAX)
+            .to_le_bytes(),
+    );
+    bytes.extend_from_slice(
+        &u16::try_from(grid.height())
+            .unwrap_or(u16::MAX)
+            .to_le_bytes(),
+    );
+    bytes.push(u8::try_from(font_height).unwrap_or(16));
+    bytes.push(flags);
+    for hex in VGA16 {
+        bytes.extend([hex >> 16, hex >> 8, hex].map(|channel| (channel & 0xFF) as u8 >> 2));
+    }
+    if let Some(font) = font {
+        bytes.extend(font.lines.iter().map(|line| line.reverse_bits()));
+    }
+    for tile in grid.tiles() {
+        bytes.push(glyph_byte(tile.ch));
+        bytes.push(attribute_from_tile(tile, ice_colors));
+    }
+    bytes
+}
+
+fn padded(text: &str, len: usize, fill: u8) -> impl Iterator<Item = u8> + '_ {
+    text.chars()
+        .map(|ch| u8::try_from(u32::from(ch)).unwrap_or(b'?'))
+        .chain(std::iter::repeat(fill))
+        .take(len)
+}
+
+// CCYYMMDD, converted from days since the epoch without pulling in a date crate.
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() / 86_400) as i64;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}{month:02}{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xbin_header(width: u16, height: u16, font_height: u8, flags: u8) -> Vec<u8> {
+        let mut bytes = XBIN_ID.to_vec();
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend([font_height, flags]);
+        bytes
+    }
+
+    fn tile(grid: &Grid, x: u32, y: u32) -> Tile {
+        *grid.get(UVec2::new(x, y)).unwrap()
+    }
+
+    #[test]
+    fn ansi_colors_and_line_breaks() {
+        let imported = import(b"\x1b[1;31mA\x1b[0mB\r\nC", Kind::Ansi).unwrap();
+        let grid = imported.grid;
+        assert_eq!(grid.width(), DEFAULT_ANSI_WIDTH);
+        let a = tile(&grid, 0, 0);
+        assert_eq!((a.ch, a.fg_index, a.bg_index), ('A', Some(12), Some(0)));
+        assert_eq!(tile(&grid, 1, 0).fg_index, Some(7));
+        assert_eq!(tile(&grid, 0, 1).ch, 'C');
+        assert!(imported.sauce.is_none());
+    }
+
+    #[test]
+    fn bright_colors_are_palette_indices() {
+        let grid = import(b"\x1b[91;104mA", Kind::Ansi).unwrap().grid;
+        let a = tile(&grid, 0, 0);
+        assert_eq!((a.fg_index, a.bg_index), (Some(12), Some(9)));
+    }
+
+    #[test]
+    fn oversized_ansi_and_bin_are_rejected() {
+        assert!(import(b"\x1b[65535Bx", Kind::Ansi).is_err());
+        assert!(import(b"\x1b[500Bx", Kind::Ansi).is_ok());
+        let cells = vec![0; (MAX_CELLS as usize + 160) * 2];
+        assert!(import(&cells, Kind::Bin).is_err());
+    }
+
+    #[test]
+    fn ansi_round_trip() {
+        let vga = Palette::builtin("vga").unwrap();
+        let mut grid = Grid::new(5, 3);
+        let cells = [('a', 12, 1, 0, 0), ('b', 3, 9, 1, 0), ('c', 7, 0, 2, 1)];
+        for (ch, fg, bg, x, y) in cells {
+            let tile = Tile::new(ch).with_attributes(Attributes::UNDERLINE);
+            let tile = tile.with_fg_index(fg, &vga).with_bg_index(bg, &vga);
+            grid.set(UVec2::new(x, y), tile);
+        }
+        let bytes = export(&grid, Kind::Ansi, None, None);
+        let mut imported = import(&bytes, Kind::Ansi).unwrap().grid;
+        // Trailing blank cells aren't written, the width comes from SAUCE.
+        assert_eq!(imported.size(), UVec2::new(5, 2));
+        imported.resize(5, 3, &vga);
+        assert_eq!(imported, grid);
+    }
+
+    #[test]
+    fn blank_cells_follow_the_terminal_palette() {
+        let solarized = Palette::builtin("solarized").unwrap();
+        let mut terminal = Terminal::fixed(2, 2);
+        terminal.recolor(&solarized);
+        // Cleared, scrolled in and resized cells.
+        terminal.feed("ab\r\ncd\x1b[2Jx\n\n".chars());
+        terminal.resize(3, 3);
+        let blank = Tile::blank(&solarized);
+        assert_eq!(blank.bg, solarized.color(0));
+        assert!(terminal.grid().tiles().iter().all(|tile| *tile == blank));
+    }
+
+    #[test]
+    fn bin_width_comes_from_sauce() {
+        let mut grid = Grid::new(4, 2);
+        let z = tile_from_attribute(b'z', 0x1E, false, None);
+        grid.set(UVec2::new(3, 1), z);
+        let imported = import(&export(&grid, Kind::Bin, None, None), Kind::Bin).unwrap();
+        assert_eq!(imported.grid.size(), UVec2::new(4, 2));
+        let z = tile(&imported.grid, 3, 1);
+        assert_eq!((z.ch, z.fg_index, z.bg_index), ('z', Some(14), Some(1)));
+    }
+
+    #[test]
+    fn sauce_round_trip() {
+        let sauce = Sauce {
+            title: "Wall".to_string(),
+            author: "someone".to_string(),
+            date: "20240102".to_string(),
+            comments: vec!["first".to_string(), "second".to_string()],
+            ..Sauce::default()
+        };
+        let bytes = export(&Grid::new(2, 1), Kind::Ansi, Some(&sauce), None);
+        let imported = import(&bytes, Kind::Ansi).unwrap().sauce.unwrap();
+        assert_eq!(imported.title, sauce.title);
+        assert_eq!(imported.author, sauce.author);
+        assert_eq!(imported.date, sauce.date);
+        assert_eq!(imported.comments, sauce.comments);
+        assert_eq!(imported.info[..2], [2, 1]);
+        assert_eq!(imported.font, "IBM VGA");
+    }
+
+    #[test]
+    fn xbin_round_trip_with_font() {
+        let mut grid = Grid::new(3, 2);
+        let hash = tile_from_attribute(b'#', 0x4F, false, None);
+        grid.set(UVec2::new(2, 1), hash);
+        let font = Font {
+            height: 8,
+            lines: (0..=u8::MAX).cycle().take(256 * 8).collect(),
+        };
+        let bytes = export(&grid, Kind::XBin, None, Some(&font));
+        let imported = import(&bytes, Kind::XBin).unwrap();
+        assert_eq!(imported.font, Some(font));
+        let hash = tile(&imported.grid, 2, 1);
+        assert_eq!(hash.ch, '#');
+        assert_eq!((hash.fg, hash.bg), (palette::vga(15), palette::vga(4)));
+    }
+
+    #[test]
+    fn xbin_compressed_runs() {
+        let mut bytes = xbin_header(2, 2, 16, XBIN_COMPRESS);
+        // Both character and attribute repeated four times.
+        bytes.extend([0xC3, b'x', 0x07]);
+        let grid = import(&bytes, Kind::XBin).unwrap().grid;
+        assert!(grid.tiles().iter().all(|tile| tile.ch == 'x'));
+    }
+
+    #[test]
+    fn xbin_rejects_bad_input() {
+        let bad_font = |height| {
+            let mut bytes = xbin_header(1, 1, height, XBIN_FONT);
+            bytes.resize(bytes.len() + 256 * 64 + 2, 0);
+            import(&bytes, Kind::XBin)
+        };
+        assert!(bad_font(0).is_err());
+        assert!(bad_font(33).is_err());
+        assert!(bad_font(32).is_ok());
+        assert!(import(&xbin_header(u16::MAX, u16::MAX, 16, 0), Kind::XBin).is_err());
+        assert!(import(&xbin_header(4, 4, 16, 0), Kind::XBin).is_err());
+        assert!(import(b"XBIN", Kind::XBin).is_err());
+    }
+
+    #[test]
+    fn fixed_screens_clamp_moves_and_scroll_on_line_feeds() {
+        let mut terminal = Terminal::fixed(3, 2);
+        let rows = |terminal: &Terminal| -> Vec<String> {
+            let row = |y| (0..3).map(|x| tile(terminal.grid(), x, y).ch).collect();
+            (0..2).map(row).collect()
+        };
+        terminal.feed("a\r\nb\x1b[5Bc\x1b[9;1Hd".chars());
+        assert_eq!(rows(&terminal), ["a  ", "dc "]);
+        // Wrapping off the bottom row and IND scroll.
+        terminal.feed("\rxyz\x1bDw".chars());
+        assert_eq!(rows(&terminal), ["xyz", "  w"]);
+        terminal.feed("\nq".chars());
+        assert_eq!(rows(&terminal), ["  w", "q  "]);
+    }
+}
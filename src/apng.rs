@@ -0,0 +1,124 @@
+use anyhow::{bail, Result};
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use image::RgbaImage;
+
+use std::io::{Seek, SeekFrom, Write};
+
+const SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+const BIT_DEPTH: u8 = 8;
+const COLOR_RGBA: u8 = 6;
+
+// Animated PNG, every frame a full RGBA image the size of the first one. The frame count in
+// `acTL` is only known at the end, `finish` goes back and rewrites the chunk.
+pub struct Encoder<W: Write + Seek> {
+    writer: W,
+    size: Option<(u32, u32)>,
+    // Offset of the `acTL` chunk.
+    control_at: u64,
+    frames: u32,
+    // `fcTL` and `fdAT` chunks share one sequence.
+    sequence: u32,
+}
+
+impl<W: Write + Seek> Encoder<W> {
+    pub const fn new(writer: W) -> Self {
+        Self {
+            writer,
+            size: None,
+            control_at: 0,
+            frames: 0,
+            sequence: 0,
+        }
+    }
+
+    pub fn encode_frame(&mut self, image: &RgbaImage, delay_ms: u32) -> Result<()> {
+        let (width, height) = image.dimensions();
+        match self.size {
+            None => self.start(width, height)?,
+            Some(size) if size != (width, height) => {
+                let (first_width, first_height) = size;
+                bail!("Frame is {width}x{height}, the first was {first_width}x{first_height}");
+            }
+            Some(_) => (),
+        }
+
+        let mut control = self.sequence.to_be_bytes().to_vec();
+        for value in [width, height, 0, 0] {
+            control.extend_from_slice(&value.to_be_bytes());
+        }
+        let delay = u16::try_from(delay_ms).unwrap_or(u16::MAX);
+        control.extend_from_slice(&delay.to_be_bytes());
+        control.extend_from_slice(&1000_u16.to_be_bytes());
+        // No disposal, frames replace the whole canvas.
+        control.extend_from_slice(&[0, 0]);
+        self.sequence += 1;
+        write_chunk(&mut self.writer, *b"fcTL", &control)?;
+
+        let pixels = compress(image)?;
+        if self.frames == 0 {
+            write_chunk(&mut self.writer, *b"IDAT", &pixels)?;
+        } else {
+            let mut data = self.sequence.to_be_bytes().to_vec();
+            data.extend(pixels);
+            self.sequence += 1;
+            write_chunk(&mut self.writer, *b"fdAT", &data)?;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        if self.frames == 0 {
+            bail!("No frames were recorded");
+        }
+        write_chunk(&mut self.writer, *b"IEND", &[])?;
+        self.writer.seek(SeekFrom::Start(self.control_at))?;
+        write_chunk(&mut self.writer, *b"acTL", &animation_control(self.frames))?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn start(&mut self, width: u32, height: u32) -> Result<()> {
+        self.size = Some((width, height));
+        self.writer.write_all(SIGNATURE)?;
+        let mut header = width.to_be_bytes().to_vec();
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[BIT_DEPTH, COLOR_RGBA, 0, 0, 0]);
+        write_chunk(&mut self.writer, *b"IHDR", &header)?;
+        self.control_at = self.writer.stream_position()?;
+        write_chunk(&mut self.writer, *b"acTL", &animation_control(0))?;
+        Ok(())
+    }
+}
+
+// Frame count and 0 plays, which loops forever.
+fn animation_control(frames: u32) -> [u8; 8] {
+    let mut data = [0; 8];
+    data[..4].copy_from_slice(&frames.to_be_bytes());
+    data
+}
+
+// Rows with filter type 0, zlib compressed.
+fn compress(image: &RgbaImage) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    let row = image.width() as usize * 4;
+    for line in image.as_raw().chunks(row.max(1)) {
+        encoder.write_all(&[0])?;
+        encoder.write_all(line)?;
+    }
+    Ok(encoder.finish()?)
+}
+
+fn write_chunk(writer: &mut impl Write, kind: [u8; 4], data: &[u8]) -> Result<()> {
+    let length = u32::try_from(data.len())?;
+    let mut crc = Crc::new();
+    crc.update(&kind);
+    crc.update(data);
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(&kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc.sum().to_be_bytes())?;
+    Ok(())
+}
@@ -13,18 +13,38 @@ use serde::{Deserialize, Serialize};
 
 use std::io::{self, BufReader, Read, Write};
 use std::net::{TcpListener, TcpStream};
+use std::fs;
+use std::path::Path;
 use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::time::{Instant, SystemTime};
+
+const CONFIG_POLL_FRAMES: u64 = 30;
 
 use crate::{
-    game::Game,
-    screenshot::{Format, Screenshot},
+    config::Config,
+    export::TextFormat,
+    game::{Game, RecordRequest},
+    keymap::Mode,
+    pane::Placement,
+    record::Recording,
+    replay::{InputEvent, InputRecorder, InputReplay},
+    screenshot::{self, Format, Region, Screenshot},
+    stats::{FrameTimer, Phase},
+    timestep::{Pacer, Timestep},
 };
 
 pub struct App {
     viewport_size: UVec2,
 
     current_frame: u64,
-    sleep_ms_per_frame: u64,
+    timestep: Timestep,
+    pacer: Pacer,
+    // Update count of the current frame read from a replay, 1 when it has none.
+    replayed_updates: Option<u32>,
+    // Replayed stdin lines wait for `input`, where live ones are read.
+    replayed_stdin: Vec<String>,
+    timer: FrameTimer,
+    config_modified: Option<SystemTime>,
     mouse: Mouse,
     keyboard: Keyboard,
     is_fullscreen: bool,
@@ -35,20 +55,92 @@ pub struct App {
     tcp_listener: TcpListener,
 
     screenshot: Screenshot,
+    // Requests from the socket keep their stream open until the frame is drawn.
+    pending_screenshots: Vec<(screenshot::Request, Option<TcpStream>)>,
+    recording: Option<Recording>,
+    input_recorder: Option<InputRecorder>,
+    // While replaying, live input is ignored and stdin lines wait in the channel.
+    input_replay: Option<InputReplay>,
 
     game: Game,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub enum AppRequest {
     Shutdown,
     GetKeyboard,
     Ping,
     Command(String),
+    Save(String),
+    Load(String),
+    // Without a path autosaving stops, the interval defaults to a minute like the command.
+    Autosave {
+        #[serde(default)]
+        path: Option<String>,
+        #[serde(default = "autosave_interval")]
+        seconds: u64,
+    },
+    Import(String),
+    Export(String),
+    GetText(TextFormat),
+    // Frame timings and counters as JSON.
+    Stats,
+    // Without a path the encoded image is sent back instead of written to disk.
+    Screenshot {
+        #[serde(default)]
+        path: Option<String>,
+        #[serde(default)]
+        format: Format,
+        #[serde(default)]
+        region: Option<Region>,
+    },
+    // Without a path the current recording is stopped.
+    Record {
+        #[serde(default)]
+        path: Option<String>,
+        #[serde(default = "every_frame")]
+        every: u64,
+    },
+    Palette(String),
+    // asciicast text recording, stopped without a path.
+    Cast(Option<String>),
+    Play {
+        #[serde(default)]
+        path: Option<String>,
+        #[serde(default = "normal_speed")]
+        speed: f64,
+    },
+    // Answers with the id of the new pane.
+    Split {
+        pane: u32,
+        placement: Placement,
+    },
+    ClosePane(u32),
+    // Widget clicks, selections and submissions since the last ask, as JSON.
+    UiEvents,
+    // Hover, press, drag, release, click and scroll in grid cells since the last ask, as JSON.
+    MouseEvents,
+    // The grid cell under a window pixel, `null` outside the wall.
+    CellAt {
+        x: f32,
+        y: f32,
+    },
+}
+
+const fn every_frame() -> u64 {
+    1
+}
+
+const fn normal_speed() -> f64 {
+    1.0
+}
+
+const fn autosave_interval() -> u64 {
+    60
 }
 
 impl App {
-    pub fn new(viewport_size: UVec2, config: crate::config::Config, rx: Receiver<String>) -> Self {
+    pub fn new(viewport_size: UVec2, config: Config, rx: Receiver<String>) -> Self {
         let mut tcp_listener =
             TcpListener::bind("127.0.0.1:2434").expect("Couldn't bind to port 2434");
         tcp_listener
@@ -59,7 +151,12 @@ impl App {
             viewport_size,
 
             current_frame: 0,
-            sleep_ms_per_frame: config.sleep_ms_per_frame,
+            timestep: Timestep::new(config.update_hz, config.max_updates_per_frame),
+            pacer: Pacer::new(config.target_fps, config.vsync),
+            replayed_updates: None,
+            replayed_stdin: Vec::new(),
+            timer: FrameTimer::new(),
+            config_modified: config.path().and_then(modified),
             mouse: Mouse::new(),
             keyboard: Keyboard::new(),
             is_fullscreen: false,
@@ -69,35 +166,318 @@ impl App {
             stdin_channel: rx,
             tcp_listener,
 
-            screenshot: Screenshot::new("screenshots".to_string()),
+            screenshot: Screenshot::new(config.screenshot_folder.clone()),
+            pending_screenshots: Vec::new(),
+            recording: None,
+            input_recorder: None,
+            input_replay: None,
 
             game: Game::new(config),
         }
     }
 
     pub fn game_loop(&mut self, helper: &mut WindowHelper<()>, graphics: &mut Graphics2D) {
-        if self.keyboard.pressed.contains(&VirtualKeyCode::Escape) || self.is_shutting_down {
+        if self.game.quitting() || self.is_shutting_down {
             helper.terminate_loop();
         }
         if self.current_frame == 0 {
             self.setup(graphics);
         }
+        if self.current_frame % CONFIG_POLL_FRAMES == 0 {
+            self.reload_config(helper);
+        }
+        self.replay_input();
+        let start = Instant::now();
         self.serve();
+        self.timer.record(Phase::Serve, start.elapsed());
+        let start = Instant::now();
         self.input();
+        self.timer.record(Phase::Input, start.elapsed());
 
-        self.update();
-
-        self.draw(graphics);
+        let pending_commands = self.game.pending_commands();
+        let start = Instant::now();
+        let updates = self.update();
+        self.timer.record(Phase::Update, start.elapsed());
 
-        if self.keyboard.just_pressed.contains(&VirtualKeyCode::F1) {
-            self.screenshot.capture(graphics, Format::Jpeg);
+        let start = Instant::now();
+        if let Err(err) = self.game.upload_font(graphics) {
+            self.game.report(format!("{err:#}"));
         }
+        let tiles_drawn = self.draw(graphics);
+        self.timer.record(Phase::Draw, start.elapsed());
+
+        self.capture_screenshots(graphics);
+        for request in self.game.take_recordings() {
+            match request {
+                RecordRequest::Start(path, every) => {
+                    self.set_recording(Some((path, every)));
+                }
+                RecordRequest::Stop => {
+                    self.set_recording(None);
+                }
+                RecordRequest::Toggle => self.toggle_recording(),
+            }
+        }
+        // A failed capture means the encoder stopped, finishing the recording reports why.
+        let stopped = self.recording.as_ref().is_some_and(|recording| {
+            let grab = || screenshot::grab(graphics, None);
+            recording.capture(self.current_frame, grab).is_err()
+        });
+        if stopped {
+            self.set_recording(None);
+        }
+        if let Some(recorder) = &mut self.input_recorder {
+            if let Err(err) = recorder.flush() {
+                eprintln!("{err:#}");
+            }
+        }
+        self.timer.frame(updates, tiles_drawn, pending_commands);
         self.current_frame += 1;
-        std::thread::sleep(std::time::Duration::from_millis(self.sleep_ms_per_frame));
+        self.pacer.wait();
         self.keyboard.clear();
+        self.mouse.clear();
         helper.request_redraw();
     }
 
+    // Chords from the keymap run as commands, only the global ones while text is typed.
+    fn apply_bindings(&mut self) {
+        let modes: &[Mode] = if self.game.wants_text_input() {
+            &[Mode::Global]
+        } else if self.game.editing() {
+            &[Mode::Global, Mode::Editor]
+        } else {
+            &[Mode::Global, Mode::Wall]
+        };
+        let commands = self.game.config().keymap.commands(modes, &self.keyboard);
+        for command in commands {
+            self.game.apply_command(&command);
+        }
+    }
+
+    fn capture_screenshots(&mut self, graphics: &mut Graphics2D) {
+        let mut requests = Vec::new();
+        // Commands without a path save to the next free name in the screenshot folder.
+        for mut request in self.game.take_screenshots() {
+            if request.path.is_none() {
+                match self.screenshot.next_path(request.format.extension()) {
+                    Ok(path) => request.path = Some(path.display().to_string()),
+                    Err(err) => {
+                        self.game.report(format!("Screenshot failed: {err:#}"));
+                        continue;
+                    }
+                }
+            }
+            requests.push((request, None));
+        }
+        let pending: Vec<_> = requests
+            .into_iter()
+            .chain(std::mem::take(&mut self.pending_screenshots))
+            .collect();
+        for (request, stream) in pending {
+            let crop = request
+                .region
+                .map(|region| {
+                    self.game
+                        .screen_area(region)
+                        .ok_or_else(|| anyhow::anyhow!("the region isn't on screen"))
+                })
+                .transpose();
+            let result = crop.and_then(|crop| {
+                let image = screenshot::grab(graphics, crop);
+                match &request.path {
+                    Some(path) => screenshot::save(image, request.format, Path::new(path))
+                        .map(|()| b"OK".to_vec()),
+                    None => screenshot::encode(image, request.format),
+                }
+            });
+            let response = match result {
+                Ok(response) => {
+                    if let Some(path) = &request.path {
+                        self.game.report(format!("Saved screenshot {path}"));
+                    }
+                    response
+                }
+                Err(err) => {
+                    self.game.report(format!("Screenshot failed: {err:#}"));
+                    b"ERROR".to_vec()
+                }
+            };
+            if let Some(mut stream) = stream {
+                if let Err(err) = stream.write_all(&response) {
+                    eprintln!("{err:?}");
+                }
+            }
+        }
+    }
+
+    fn toggle_recording(&mut self) {
+        if self.recording.is_some() {
+            self.set_recording(None);
+            return;
+        }
+        match self.screenshot.next_path("gif") {
+            Ok(path) => {
+                self.set_recording(Some((path.display().to_string(), 1)));
+            }
+            Err(err) => self.game.report(format!("Recording failed: {err:#}")),
+        }
+    }
+
+    fn set_recording(&mut self, recording: Option<(String, u64)>) -> bool {
+        if let Some(finished) = self.recording.take() {
+            let path = finished.path.display().to_string();
+            let message = match finished.finish() {
+                Ok(frames) => format!("Recorded {frames} frames to {path}"),
+                Err(err) => format!("Recording failed: {err:#}"),
+            };
+            self.game.report(message);
+        }
+        let Some((path, every)) = recording else {
+            return true;
+        };
+        // Uncapped frames have no fixed length, the update period is the closest thing.
+        let frame = self
+            .pacer
+            .frame_duration()
+            .unwrap_or_else(|| self.timestep.step());
+        match Recording::start(Path::new(&path), every, frame) {
+            Ok(recording) => {
+                self.game.report(format!("Recording to {path}"));
+                self.recording = Some(recording);
+                true
+            }
+            Err(err) => {
+                self.game.report(format!("Recording failed: {err:#}"));
+                false
+            }
+        }
+    }
+
+    // Applies `config.txt` edits while running, a broken file keeps the current settings.
+    fn reload_config(&mut self, helper: &mut WindowHelper<()>) {
+        let Some(path) = self.game.config().path().map(str::to_string) else {
+            return;
+        };
+        let modified = modified(&path);
+        if modified == self.config_modified {
+            return;
+        }
+        self.config_modified = modified;
+        if modified.is_none() {
+            self.game
+                .report(format!("{path} is gone, keeping the current settings"));
+            return;
+        }
+        let config = match Config::load(&path) {
+            Ok(config) => config,
+            Err(err) => {
+                self.game.report(format!("Config not applied: {err}"));
+                return;
+            }
+        };
+        let mut warnings = config.warnings.clone();
+        let current = self.game.config();
+        if config.vsync != current.vsync {
+            warnings.push("vsync changes apply after a restart".to_string());
+        }
+        let warnings = warnings.join("; ");
+        if config.title != current.title {
+            helper.set_title(&config.title);
+        }
+        if (config.window_width, config.window_height)
+            != (current.window_width, current.window_height)
+        {
+            // Scaled like the size the window was created with in `main`.
+            let size = UVec2::new(config.window_width, config.window_height);
+            helper.set_size_scaled_pixels(size.as_vec2());
+        }
+        self.timestep
+            .configure(config.update_hz, config.max_updates_per_frame);
+        self.pacer.set_target(config.target_fps);
+        self.screenshot.folder.clone_from(&config.screenshot_folder);
+        self.game.set_config(config);
+        if warnings.is_empty() {
+            self.game.report(format!("Reloaded {path}"));
+        } else {
+            self.game.report(format!("Reloaded {path}: {warnings}"));
+        }
+    }
+
+    pub fn record_input(&mut self, path: &Path) {
+        match InputRecorder::create(path) {
+            Ok(recorder) => self.input_recorder = Some(recorder),
+            Err(err) => self.game.report(format!("Input recording failed: {err:#}")),
+        }
+    }
+
+    pub fn start_replay(&mut self, path: &Path) {
+        match InputReplay::open(path) {
+            Ok(replay) => self.input_replay = Some(replay),
+            Err(err) => self.game.report(format!("Replay failed: {err:#}")),
+        }
+    }
+
+    fn replay_input(&mut self) {
+        let Some(replay) = &mut self.input_replay else {
+            return;
+        };
+        let events = replay.events(self.current_frame);
+        if replay.finished() {
+            self.input_replay = None;
+            self.game.report("Replay finished");
+        }
+        for event in events {
+            match event {
+                InputEvent::Stdin(line) => self.replayed_stdin.push(line),
+                event => self.apply_input(event),
+            }
+        }
+    }
+
+    fn record(&mut self, event: &InputEvent) {
+        if let Some(recorder) = &mut self.input_recorder {
+            if let Err(err) = recorder.record(self.current_frame, event) {
+                eprintln!("{err:#}");
+            }
+        }
+    }
+
+    fn live_input(&mut self, event: InputEvent) {
+        if self.input_replay.is_none() {
+            self.apply_input(event);
+        }
+    }
+
+    fn apply_input(&mut self, event: InputEvent) {
+        self.record(&event);
+        match event {
+            InputEvent::KeyDown(key_code) => self.keyboard.press(key_code),
+            InputEvent::KeyUp(key_code) => self.keyboard.release(key_code),
+            InputEvent::Char(ch) => {
+                if self.is_inputting_text {
+                    self.keyboard.buffer.push(ch);
+                }
+            }
+            InputEvent::Modifiers(state) => self.keyboard.modifiers = state,
+            InputEvent::MouseMove([x, y]) => self.mouse.position = Vec2::new(x, y),
+            InputEvent::MouseDown(button) => self.mouse.press(button.into()),
+            InputEvent::MouseUp(button) => self.mouse.release(button.into()),
+            InputEvent::Scroll(lines) => self.mouse.scroll_lines += lines,
+            InputEvent::Resize([width, height]) => {
+                eprintln!("new size: {width}x{height}");
+                self.viewport_size = UVec2::new(width, height);
+            }
+            InputEvent::ScaleFactor(scale_factor) => self.game.set_scale_factor(scale_factor),
+            InputEvent::Updates(updates) => self.replayed_updates = Some(updates),
+            InputEvent::Stdin(line) => self.game.apply_command(&line),
+            InputEvent::Request(request) => {
+                if let Err(e) = self.handle_request(request, None) {
+                    eprintln!("{e:?}");
+                }
+            }
+        }
+    }
+
     pub fn setup(&mut self, graphics: &mut Graphics2D) {
         self.game.setup(graphics);
     }
@@ -107,6 +487,7 @@ impl App {
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
+                    self.timer.connection();
                     //stream.set_nonblocking(true).expect("set_nonblocking call failed");
                     if let Err(e) = self.handle_connection(stream) {
                         eprintln!("{e:?}");
@@ -131,62 +512,211 @@ impl App {
         let recieved = String::from_utf8_lossy(&buf);
         let recieved = dbg!(recieved.trim_end_matches('\0'));
         let deserialized: AppRequest = serde_json::from_str(&recieved).unwrap();
-        match deserialized {
+        if self.input_replay.is_some() {
+            return stream.write_all(b"ERROR");
+        }
+        // Polling requests don't change anything and would flood the recording.
+        if !matches!(
+            deserialized,
+            AppRequest::GetKeyboard
+                | AppRequest::Ping
+                | AppRequest::Stats
+                | AppRequest::UiEvents
+                | AppRequest::MouseEvents
+                | AppRequest::CellAt { .. }
+        ) {
+            self.record(&InputEvent::Request(deserialized.clone()));
+        }
+        self.handle_request(deserialized, Some(stream))
+    }
+
+    // Replayed requests have no stream and their responses are dropped.
+    fn handle_request(
+        &mut self,
+        request: AppRequest,
+        mut stream: Option<TcpStream>,
+    ) -> io::Result<()> {
+        match request {
             AppRequest::Ping => {
-                stream.write_all(b"Pong")?;
+                respond(&mut stream, b"Pong")?;
             }
             AppRequest::Shutdown => {
                 self.is_shutting_down = true;
-                stream.write_all(b"OK")?;
+                respond(&mut stream, b"OK")?;
             }
             AppRequest::GetKeyboard => {
-                stream
-                    .write_all(serde_json::to_string(&self.keyboard).unwrap().as_bytes())
-                    .unwrap();
+                respond(
+                    &mut stream,
+                    serde_json::to_string(&self.keyboard).unwrap().as_bytes(),
+                )?;
             }
             AppRequest::Command(command) => {
                 self.game.apply_command(&command);
             }
+            AppRequest::Save(path) => {
+                let saved = self.game.save(Path::new(&path));
+                respond(&mut stream, if saved { b"OK" } else { b"ERROR" })?;
+            }
+            AppRequest::Load(path) => {
+                let loaded = self.game.load(Path::new(&path));
+                respond(&mut stream, if loaded { b"OK" } else { b"ERROR" })?;
+            }
+            AppRequest::Import(path) => {
+                let imported = self.game.import(Path::new(&path));
+                respond(&mut stream, if imported { b"OK" } else { b"ERROR" })?;
+            }
+            AppRequest::Export(path) => {
+                let exported = self.game.export(Path::new(&path));
+                respond(&mut stream, if exported { b"OK" } else { b"ERROR" })?;
+            }
+            AppRequest::Stats => {
+                respond(
+                    &mut stream,
+                    serde_json::to_string(self.timer.stats())
+                        .unwrap()
+                        .as_bytes(),
+                )?;
+            }
+            AppRequest::GetText(format) => {
+                respond(&mut stream, self.game.text(format).as_bytes())?;
+            }
+            AppRequest::Screenshot {
+                path,
+                format,
+                region,
+            } => {
+                let request = screenshot::Request {
+                    path,
+                    format,
+                    region,
+                };
+                self.pending_screenshots.push((request, stream));
+            }
+            AppRequest::Record { path, every } => {
+                let started = self.set_recording(path.map(|path| (path, every)));
+                respond(&mut stream, if started { b"OK" } else { b"ERROR" })?;
+            }
+            AppRequest::Palette(name) => {
+                let switched = self.game.switch_palette(&name);
+                respond(&mut stream, if switched { b"OK" } else { b"ERROR" })?;
+            }
+            AppRequest::Cast(path) => {
+                let started = self.game.set_cast(path.as_deref().map(Path::new));
+                respond(&mut stream, if started { b"OK" } else { b"ERROR" })?;
+            }
+            AppRequest::Play { path, speed } => {
+                let playing = self
+                    .game
+                    .play(path.as_deref().map(|path| (Path::new(path), speed)));
+                respond(&mut stream, if playing { b"OK" } else { b"ERROR" })?;
+            }
+            AppRequest::Autosave { path, seconds } => {
+                self.game.set_autosave(path.map(|path| (path, seconds)));
+                respond(&mut stream, b"OK")?;
+            }
+            AppRequest::Split { pane, placement } => match self.game.split(pane, placement) {
+                Some(id) => respond(&mut stream, id.to_string().as_bytes())?,
+                None => respond(&mut stream, b"ERROR")?,
+            },
+            AppRequest::UiEvents => {
+                let events = self.game.take_ui_events();
+                respond(
+                    &mut stream,
+                    serde_json::to_string(&events).unwrap().as_bytes(),
+                )?;
+            }
+            AppRequest::MouseEvents => {
+                let events = self.game.take_mouse_events();
+                respond(
+                    &mut stream,
+                    serde_json::to_string(&events).unwrap().as_bytes(),
+                )?;
+            }
+            AppRequest::CellAt { x, y } => {
+                let cell = self
+                    .game
+                    .cell_at(Vec2::new(x, y))
+                    .map(|cell| cell.to_array());
+                respond(
+                    &mut stream,
+                    serde_json::to_string(&cell).unwrap().as_bytes(),
+                )?;
+            }
+            AppRequest::ClosePane(pane) => {
+                let closed = self.game.close_pane(pane);
+                respond(&mut stream, if closed { b"OK" } else { b"ERROR" })?;
+            }
             other => {
-                stream.write_all(b"ERROR")?;
+                respond(&mut stream, b"ERROR")?;
                 panic!("{}", format!("Unhandled app request: {other:?}"));
             }
         }
-        //stream.write(&[b'\0'])?;
         Ok(())
     }
 
     pub fn input(&mut self) {
+        self.apply_bindings();
         self.game
             .input(self.viewport_size, &self.mouse, &self.keyboard);
+        self.is_inputting_text = self.game.wants_text_input();
 
+        for line in std::mem::take(&mut self.replayed_stdin) {
+            self.apply_input(InputEvent::Stdin(line));
+        }
+        if self.input_replay.is_some() {
+            return;
+        }
         let res = self.stdin_channel.try_recv();
         match res {
-            Ok(value) => self.game.apply_command(&value),
+            Ok(value) => self.apply_input(InputEvent::Stdin(value)),
             Err(TryRecvError::Disconnected) => eprintln!("disconnected from stdin!"),
             Err(TryRecvError::Empty) => (),
         }
     }
 
-    pub fn update(&mut self) {
-        self.game.update(self.current_frame);
+    // Runs as many fixed steps as are due, so game time doesn't depend on the frame rate.
+    pub fn update(&mut self) -> u32 {
+        let due = self.timestep.advance();
+        let updates = if let Some(updates) = self.replayed_updates.take() {
+            updates
+        } else if self.input_replay.is_some() {
+            1
+        } else {
+            if due != 1 {
+                self.record(&InputEvent::Updates(due));
+            }
+            due
+        };
+        for _ in 0..updates {
+            self.game.update(self.timestep.step());
+        }
+        updates
     }
 
-    pub fn draw(&self, graphics: &mut Graphics2D) {
-        graphics.clear_screen(Color::from_gray(0.3));
-        self.game.draw(graphics);
+    pub fn draw(&self, graphics: &mut Graphics2D) -> usize {
+        graphics.clear_screen(self.game.background());
+        let drawn = self.game.draw(graphics);
+        if self.game.shows_stats() {
+            self.game
+                .draw_overlay(&self.timer.stats().lines(), graphics);
+        }
+        drawn
     }
 }
 
 impl WindowHandler for App {
+    // Recorded so a replay starts at the same size and scale, its own frame 0 events come later.
     fn on_start(&mut self, _helper: &mut WindowHelper<()>, info: WindowStartupInfo) {
-        println!("{:?}", info.viewport_size_pixels());
-        self.viewport_size = *info.viewport_size_pixels();
+        self.apply_input(InputEvent::Resize(info.viewport_size_pixels().to_array()));
+        self.apply_input(InputEvent::ScaleFactor(info.scale_factor()));
     }
 
     fn on_resize(&mut self, _helper: &mut WindowHelper<()>, size_pixels: UVec2) {
-        println!("new size: {size_pixels:?}");
-        self.viewport_size = size_pixels;
+        self.live_input(InputEvent::Resize(size_pixels.to_array()));
+    }
+
+    fn on_scale_factor_changed(&mut self, _helper: &mut WindowHelper<()>, scale_factor: f64) {
+        self.live_input(InputEvent::ScaleFactor(scale_factor));
     }
 
     fn on_mouse_grab_status_changed(
@@ -195,18 +725,18 @@ impl WindowHandler for App {
         mouse_grabbed: bool,
     ) {
         if mouse_grabbed {
-            println!("Mouse grabbed!");
+            eprintln!("Mouse grabbed!");
         } else {
-            println!("Mouse ungrabbed!");
+            eprintln!("Mouse ungrabbed!");
         }
         self.mouse.grabbed = mouse_grabbed;
     }
 
     fn on_fullscreen_status_changed(&mut self, _helper: &mut WindowHelper<()>, fullscreen: bool) {
         if fullscreen {
-            println!("App is now in fullscreen!");
+            eprintln!("App is now in fullscreen!");
         } else {
-            println!("App is now windowed!");
+            eprintln!("App is now windowed!");
         }
         self.is_fullscreen = fullscreen;
     }
@@ -216,15 +746,15 @@ impl WindowHandler for App {
     }
 
     fn on_mouse_move(&mut self, _helper: &mut WindowHelper<()>, position: Vec2) {
-        self.mouse.position = position;
+        self.live_input(InputEvent::MouseMove(position.to_array()));
     }
 
     fn on_mouse_button_down(&mut self, _helper: &mut WindowHelper<()>, button: MouseButton) {
-        self.mouse.press(button);
+        self.live_input(InputEvent::MouseDown(button.into()));
     }
 
     fn on_mouse_button_up(&mut self, _helper: &mut WindowHelper<()>, button: MouseButton) {
-        self.mouse.release(button);
+        self.live_input(InputEvent::MouseUp(button.into()));
     }
 
     fn on_mouse_wheel_scroll(
@@ -241,7 +771,7 @@ impl WindowHandler for App {
                     eprintln!("Unsupported input: MouseScroll on Z coordinate!");
                 }
                 if y != 0.0 {
-                    self.mouse.scroll_lines += y;
+                    self.live_input(InputEvent::Scroll(y));
                 }
             }
             other => eprintln!("Unsupported input: {other:?}"),
@@ -255,7 +785,7 @@ impl WindowHandler for App {
         _scancode: KeyScancode,
     ) {
         if let Some(key_code) = virtual_key_code {
-            self.keyboard.press(key_code);
+            self.live_input(InputEvent::KeyDown(key_code));
         }
     }
 
@@ -266,14 +796,12 @@ impl WindowHandler for App {
         _scancode: KeyScancode,
     ) {
         if let Some(key_code) = virtual_key_code {
-            self.keyboard.release(key_code);
+            self.live_input(InputEvent::KeyUp(key_code));
         }
     }
 
     fn on_keyboard_char(&mut self, _helper: &mut WindowHelper<()>, unicode_codepoint: char) {
-        if self.is_inputting_text {
-            self.keyboard.buffer.push(unicode_codepoint);
-        }
+        self.live_input(InputEvent::Char(unicode_codepoint));
     }
 
     fn on_keyboard_modifiers_changed(
@@ -281,7 +809,7 @@ impl WindowHandler for App {
         _helper: &mut WindowHelper<()>,
         state: ModifiersState,
     ) {
-        self.keyboard.modifiers = state;
+        self.live_input(InputEvent::Modifiers(state));
     }
 }
 
@@ -306,13 +834,14 @@ impl Keyboard {
     }
 
     pub fn clear(&mut self) {
+        self.buffer.clear();
         self.just_pressed.clear();
         self.just_released.clear();
     }
 
     pub fn press(&mut self, button: VirtualKeyCode) {
         if self.pressed.contains(&button) {
-            println!("Pressed {button:?} without releasing it first!");
+            eprintln!("Pressed {button:?} without releasing it first!");
         } else {
             self.pressed.push(button);
             self.just_pressed.push(button);
@@ -326,7 +855,7 @@ impl Keyboard {
                 self.just_released.push(button);
             }
         } else {
-            println!("Released {button:?} without it being pressed!");
+            eprintln!("Released {button:?} without it being pressed!");
         }
     }
 }
@@ -348,9 +877,26 @@ impl Mouse {
         }
     }
 
+    pub const fn position(&self) -> Vec2 {
+        self.position
+    }
+
+    // Wheel lines since the last frame, positive when scrolling up.
+    pub const fn scroll_lines(&self) -> f64 {
+        self.scroll_lines
+    }
+
+    pub const fn clear(&mut self) {
+        self.scroll_lines = 0.0;
+    }
+
+    pub fn is_pressed(&self, button: MouseButton) -> bool {
+        self.pressed.contains(&button)
+    }
+
     pub fn press(&mut self, button: MouseButton) {
         if self.pressed.contains(&button) {
-            println!("Pressed {button:?} without releasing it first!");
+            eprintln!("Pressed {button:?} without releasing it first!");
         } else {
             self.pressed.push(button);
         }
@@ -362,7 +908,34 @@ impl Mouse {
                 self.pressed.remove(idx);
             }
         } else {
-            println!("Released {button:?} without it being pressed!");
+            eprintln!("Released {button:?} without it being pressed!");
         }
     }
 }
+
+fn respond(stream: &mut Option<TcpStream>, bytes: &[u8]) -> io::Result<()> {
+    match stream {
+        Some(stream) => stream.write_all(bytes),
+        None => Ok(()),
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn autosave_requests_default_to_a_minute() {
+        let request = serde_json::from_str(r#"{"Autosave":{"path":"wall.json"}}"#).unwrap();
+        let AppRequest::Autosave { path, seconds } = request else {
+            panic!("expected an autosave request");
+        };
+        assert_eq!((path.as_deref(), seconds), (Some("wall.json"), 60));
+        let stop = serde_json::from_str(r#"{"Autosave":{}}"#).unwrap();
+        assert!(matches!(stop, AppRequest::Autosave { path: None, .. }));
+    }
+}
@@ -0,0 +1,29 @@
+use anyhow::{bail, Result};
+
+// Reads the little-endian fields of the binary save and .xp formats.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub const fn new(bytes: &'a [u8], offset: usize) -> Self {
+        Self { bytes, offset }
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let Some(slice) = self.bytes.get(self.offset..self.offset + len) else {
+            bail!("Unexpected end of data at byte {}", self.offset);
+        };
+        self.offset += len;
+        Ok(slice)
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    pub fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+}
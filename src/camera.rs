@@ -0,0 +1,114 @@
+use glam::{IVec2, UVec2, Vec2};
+use speedy2d::window::{MouseButton, VirtualKeyCode};
+
+use std::str::FromStr;
+
+use crate::app::{Keyboard, Mouse};
+
+const SCROLL_CELLS: f64 = 3.0;
+const SHIFT_PAN_CELLS: i32 = 10;
+pub const MAX_ZOOM: u32 = 8;
+
+// How many screen pixels one font pixel takes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Zoom {
+    // The largest level that fits the whole wall, but never below the display's scale factor.
+    #[default]
+    Auto,
+    Level(u32),
+}
+
+impl FromStr for Zoom {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "auto" => Ok(Self::Auto),
+            level => match level.parse() {
+                Ok(level @ 1..=MAX_ZOOM) => Ok(Self::Level(level)),
+                _ => Err(format!("`{level}` is not auto or 1 to {MAX_ZOOM}")),
+            },
+        }
+    }
+}
+
+// Which part of the wall is on screen, `offset` is the grid cell in the top left corner.
+pub struct Camera {
+    pub offset: UVec2,
+    // Mouse position and offset when the middle button went down.
+    drag: Option<(Vec2, UVec2)>,
+}
+
+impl Camera {
+    pub const fn new() -> Self {
+        Self {
+            offset: UVec2::ZERO,
+            drag: None,
+        }
+    }
+
+    // Arrow keys are left alone while something else, like the text tool, uses them.
+    pub fn input(
+        &mut self,
+        mouse: &Mouse,
+        keyboard: &Keyboard,
+        cell_size: UVec2,
+        use_arrows: bool,
+    ) {
+        let step = if keyboard.modifiers.shift() {
+            SHIFT_PAN_CELLS
+        } else {
+            1
+        };
+        let mut delta = IVec2::ZERO;
+        if use_arrows {
+            for key in &keyboard.just_pressed {
+                match key {
+                    VirtualKeyCode::Left => delta.x -= step,
+                    VirtualKeyCode::Right => delta.x += step,
+                    VirtualKeyCode::Up => delta.y -= step,
+                    VirtualKeyCode::Down => delta.y += step,
+                    _ => (),
+                }
+            }
+        }
+
+        // Ctrl+wheel zooms instead.
+        let lines = if keyboard.modifiers.ctrl() {
+            0
+        } else {
+            (-mouse.scroll_lines() * SCROLL_CELLS).round() as i32
+        };
+        if keyboard.modifiers.shift() {
+            delta.x += lines;
+        } else {
+            delta.y += lines;
+        }
+        self.pan(delta);
+
+        if !mouse.is_pressed(MouseButton::Middle) {
+            self.drag = None;
+            return;
+        }
+        let (start, offset) = *self.drag.get_or_insert((mouse.position(), self.offset));
+        let moved = ((start - mouse.position()) / cell_size.max(UVec2::ONE).as_vec2()).round();
+        self.offset = offset;
+        self.pan(moved.as_ivec2());
+    }
+
+    pub fn pan(&mut self, delta: IVec2) {
+        self.offset = (self.offset.as_ivec2() + delta).max(IVec2::ZERO).as_uvec2();
+    }
+
+    // Keeps the view inside the grid, a grid smaller than the view stays in the corner.
+    pub fn clamp(&mut self, grid_size: UVec2, view: UVec2) {
+        self.offset = self.offset.min(grid_size.max(view) - view);
+    }
+
+    // Scrolls just far enough to show `cell`.
+    pub fn follow(&mut self, cell: UVec2, view: UVec2) {
+        let view = view.max(UVec2::ONE);
+        let end = cell + UVec2::ONE;
+        self.offset = self.offset.min(cell).max(end.max(view) - view);
+    }
+}
@@ -0,0 +1,243 @@
+use anyhow::{bail, Context, Result};
+use glam::UVec2;
+use serde::Deserialize;
+
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::ansi::Terminal;
+use crate::cp437::{from_unicode_or_control, to_unicode};
+use crate::export::sgr;
+use crate::grid::{Grid, MAX_CELLS};
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+    width: u32,
+    height: u32,
+}
+
+// Writes asciinema v2 `.cast` files, one output event per frame in which the grid changed.
+pub struct Recorder {
+    pub path: PathBuf,
+    writer: BufWriter<File>,
+    start: Instant,
+    last: Grid,
+}
+
+impl Recorder {
+    pub fn start(path: &Path, grid: &Grid) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("Couldn't create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let header = serde_json::json!({
+            "version": 2,
+            "width": grid.width(),
+            "height": grid.height(),
+            "timestamp": timestamp,
+            "env": { "TERM": "xterm-256color" },
+        });
+        writeln!(writer, "{header}")?;
+        let mut recorder = Self {
+            path: path.to_path_buf(),
+            writer,
+            start: Instant::now(),
+            last: grid.clone(),
+        };
+        recorder.write_event(format!("\x1b[2J{}", diff(None, grid)))?;
+        Ok(recorder)
+    }
+
+    pub fn capture(&mut self, grid: &Grid) -> Result<()> {
+        if *grid == self.last {
+            return Ok(());
+        }
+        let changes = if grid.size() == self.last.size() {
+            diff(Some(&self.last), grid)
+        } else {
+            format!("\x1b[2J{}", diff(None, grid))
+        };
+        self.last = grid.clone();
+        self.write_event(changes)
+    }
+
+    fn write_event(&mut self, data: String) -> Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+        writeln!(
+            self.writer,
+            "{}",
+            serde_json::to_string(&(time, "o", data))?
+        )?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// ANSI text that turns `before` into `after`, or draws all of `after` on a cleared screen.
+fn diff(before: Option<&Grid>, after: &Grid) -> String {
+    let mut text = String::new();
+    let mut cursor = None;
+    let mut style = None;
+    for (y, row) in after.rows().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            let position = UVec2::new(x as u32, y as u32);
+            if before.and_then(|grid| grid.get(position)) == Some(tile) {
+                continue;
+            }
+            if cursor != Some(position) {
+                let _ = write!(text, "\x1b[{};{}H", y + 1, x + 1);
+            }
+            let tile_style = (tile.fg, tile.bg, tile.attributes);
+            if style != Some(tile_style) {
+                text.push_str(&sgr(tile));
+                style = Some(tile_style);
+            }
+            text.push(to_unicode(tile.ch));
+            cursor = (position.x + 1 < after.width()).then_some(position + UVec2::X);
+        }
+    }
+    if style.is_some() {
+        text.push_str("\x1b[0m");
+    }
+    text
+}
+
+// Replays the output events of a `.cast` file into a fixed size terminal.
+pub struct Player {
+    pub path: PathBuf,
+    events: Vec<(f64, String)>,
+    next: usize,
+    start: Instant,
+    speed: f64,
+    terminal: Terminal,
+    // Start of an escape sequence that continues in the next event.
+    pending: String,
+}
+
+impl Player {
+    pub fn open(path: &Path, speed: f64) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header: Header = serde_json::from_str(lines.next().context("Empty .cast file")?)
+            .context("Invalid .cast header")?;
+        if header.version != 2 {
+            bail!("Unsupported asciicast version {}", header.version);
+        }
+        let (width, height) = (header.width, header.height);
+        if width == 0 || height == 0 || width.saturating_mul(height) > MAX_CELLS {
+            bail!("Unsupported .cast size {width}x{height}");
+        }
+        let mut events = Vec::new();
+        for (idx, line) in lines.enumerate() {
+            let (time, kind, data): (f64, String, String) = serde_json::from_str(line)
+                .with_context(|| format!("Invalid .cast event on line {}", idx + 2))?;
+            if kind == "o" {
+                events.push((time, data));
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            events,
+            next: 0,
+            start: Instant::now(),
+            speed: if speed > 0.0 { speed } else { 1.0 },
+            terminal: Terminal::fixed(width, height),
+            pending: String::new(),
+        })
+    }
+
+    // Feeds every event that is due, returns whether the screen changed.
+    pub fn advance(&mut self) -> bool {
+        let now = self.start.elapsed().as_secs_f64() * self.speed;
+        let mut changed = false;
+        while let Some((time, data)) = self.events.get(self.next) {
+            if *time > now {
+                break;
+            }
+            let data = std::mem::take(&mut self.pending) + data;
+            let (complete, rest) = split_incomplete(&data);
+            self.terminal
+                .feed(complete.chars().map(from_unicode_or_control));
+            self.pending = rest.to_string();
+            self.next += 1;
+            changed = true;
+        }
+        changed
+    }
+
+    pub const fn finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+
+    pub const fn grid(&self) -> &Grid {
+        self.terminal.grid()
+    }
+}
+
+fn split_incomplete(data: &str) -> (&str, &str) {
+    let Some(start) = data.rfind('\x1b') else {
+        return (data, "");
+    };
+    let sequence = &data[start + 1..];
+    let complete = match sequence.strip_prefix('[') {
+        Some(csi) => csi.chars().any(|ch| ('@'..='~').contains(&ch)),
+        None => !sequence.is_empty(),
+    };
+    if complete {
+        (data, "")
+    } else {
+        data.split_at(start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{color_to_rgba, Tile};
+    use speedy2d::color::Color;
+
+    fn cast_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("grid_renderer-{}-{name}.cast", std::process::id()))
+    }
+
+    #[test]
+    fn recording_plays_back() {
+        let path = cast_path("round-trip");
+        let mut grid = Grid::new(4, 2);
+        grid.set(UVec2::new(1, 0), Tile::new('a').with_fg(Color::RED));
+        let mut recorder = Recorder::start(&path, &grid).unwrap();
+        grid.set(UVec2::new(3, 1), Tile::new('\u{db}').with_bg(Color::BLUE));
+        recorder.capture(&grid).unwrap();
+        drop(recorder);
+
+        let mut player = Player::open(&path, 1e9).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(player.advance());
+        assert!(player.finished());
+        assert_eq!(player.grid().size(), grid.size());
+        for (played, recorded) in player.grid().tiles().iter().zip(grid.tiles()) {
+            assert_eq!(played.ch, recorded.ch);
+            assert_eq!(color_to_rgba(played.fg), color_to_rgba(recorded.fg));
+            assert_eq!(color_to_rgba(played.bg), color_to_rgba(recorded.bg));
+        }
+    }
+
+    #[test]
+    fn rejects_bad_sizes() {
+        for (name, width, height) in [("empty", 0, 3), ("huge", 65535, 65535)] {
+            let path = cast_path(name);
+            let header = serde_json::json!({ "version": 2, "width": width, "height": height });
+            fs::write(&path, header.to_string()).unwrap();
+            let opened = Player::open(&path, 1.0);
+            fs::remove_file(&path).unwrap();
+            assert!(opened.is_err(), "{name}");
+        }
+    }
+}
@@ -1,79 +1,360 @@
-use confargenv::fusion;
-
-use std::collections::HashMap;
 use std::default::Default;
 use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+use crate::camera::Zoom;
+use crate::game::CursorStyle;
+use crate::keymap::Keymap;
+use crate::palette::{self, Palette};
 
+// Values come from `Default`, then the config file, then `--key=value` arguments.
 pub struct Config {
     path: Option<String>,
+    // Window title.
     pub title: String,
-    pub sleep_ms_per_frame: u64,
+    // Frames drawn per second, 0 leaves pacing to vsync or runs uncapped without it.
+    pub target_fps: u32,
+    // Game updates per second, independent of the frame rate.
+    pub update_hz: u32,
+    // Updates allowed in one frame when catching up after a stall.
+    pub max_updates_per_frame: u32,
+    // Only read when the window is created.
+    pub vsync: bool,
+    // Initial window size in pixels.
     pub window_width: u32,
     pub window_height: u32,
+    // Size of one grid cell in pixels.
     pub grid_width: u32,
     pub grid_height: u32,
+    // Cell scale, `auto` or 1 to 8.
+    pub zoom: Zoom,
+    // Periodically save the wall to this file, empty to disable.
+    pub autosave_path: String,
+    pub autosave_seconds: u64,
+    // Where `screenshot` and `record` without a path write, F1 and F3 by default.
+    pub screenshot_folder: String,
+    // Active palette, `vga`, `xterm`, `solarized` or a `palette.<name>` from the config.
+    pub palette: String,
+    pub palettes: Vec<Palette>,
+    // Window colour around and behind the grid, hex.
+    pub background: u32,
+    // `block`, `underline` or `bar`.
+    pub cursor_style: CursorStyle,
+    // Time the cursor stays on and off, 0 keeps it steady.
+    pub cursor_blink_ms: u64,
+    // Defaults plus the `bind.<mode>.<chord>` entries of the config.
+    pub keymap: Keymap,
+    // Unknown keys and similar problems that didn't stop loading.
+    pub warnings: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            path: None,
+            title: "FLOATING".to_string(),
+            target_fps: 60,
+            update_hz: 60,
+            max_updates_per_frame: 5,
+            vsync: true,
+            window_width: 640,
+            window_height: 480,
+            grid_width: 8,
+            grid_height: 16,
+            zoom: Zoom::Auto,
+            autosave_path: String::new(),
+            autosave_seconds: 60,
+            screenshot_folder: "screenshots".to_string(),
+            palette: "vga".to_string(),
+            palettes: Vec::new(),
+            background: 0x4D_4D4D,
+            cursor_style: CursorStyle::Block,
+            cursor_blink_ms: 500,
+            keymap: Keymap::default(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+const KEYS: [&str; 17] = [
+    "title",
+    "target_fps",
+    "update_hz",
+    "max_updates_per_frame",
+    "vsync",
+    "window_width",
+    "window_height",
+    "grid_width",
+    "grid_height",
+    "zoom",
+    "autosave_path",
+    "autosave_seconds",
+    "screenshot_folder",
+    "palette",
+    "background",
+    "cursor_style",
+    "cursor_blink_ms",
+];
+
+#[derive(Debug)]
+pub struct ConfigError(Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join("; "))
+    }
 }
 
+impl Error for ConfigError {}
+
 impl Config {
+    // Invalid values fall back to their defaults and are printed.
     pub fn new(path: &str) -> Self {
+        let (config, errors) = Self::read(path);
+        for error in &errors {
+            eprintln!("{error}");
+        }
+        for warning in &config.warnings {
+            eprintln!("{warning}");
+        }
+        config
+    }
+
+    // Fails on any invalid value, for reloading without disturbing a running session.
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        let (config, errors) = Self::read(path);
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(errors))
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    fn read(path: &str) -> (Self, Vec<String>) {
+        let (text, error) = match fs::read_to_string(path) {
+            Ok(text) => (text, None),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (String::new(), None),
+            Err(err) => (String::new(), Some(format!("{path}: {err}"))),
+        };
+        let (config, errors) = Self::from_text(path, &text, std::env::args().skip(1));
+        (config, error.into_iter().chain(errors).collect())
+    }
+
+    // `path` only labels the errors, `args` are the command line arguments.
+    fn from_text(
+        path: &str,
+        text: &str,
+        args: impl Iterator<Item = String>,
+    ) -> (Self, Vec<String>) {
         let mut config = Self {
             path: Some(path.to_string()),
             ..Default::default()
         };
-        config = match config.reload() {
-            Ok(config) => config,
-            Err(err) => {
-                eprintln!("{err}");
-                Self {
-                    path: Some(path.to_string()),
-                    ..Default::default()
+        let mut errors = Vec::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let location = format!("{path}:{}", idx + 1);
+            let Some((key, value)) = line.split_once('=') else {
+                errors.push(format!("{location}: expected key=value, got `{line}`"));
+                continue;
+            };
+            let key = key.trim();
+            if let Some(name) = key.strip_prefix("palette.") {
+                match Palette::parse(name, value) {
+                    Ok(palette) => config.palettes.push(palette),
+                    Err(err) => errors.push(format!("{location}: {key}: {err}")),
                 }
+                continue;
             }
-        };
-        config
+            if let Some(binding) = key.strip_prefix("bind.") {
+                if let Err(err) = config.keymap.parse(binding, value) {
+                    errors.push(format!("{location}: {key}: {err}"));
+                }
+                continue;
+            }
+            if !KEYS.contains(&key) {
+                config.warnings.push(format!(
+                    "{location}: unknown key `{key}`{}",
+                    suggestion(key)
+                ));
+                continue;
+            }
+            if let Err(err) = config.set(key, value.trim()) {
+                errors.push(format!("{location}: {key}: {err}"));
+            }
+        }
+
+        // Other `--flag=value` arguments belong to the command line modes.
+        for arg in args {
+            let Some((key, value)) = arg.strip_prefix("--").and_then(|arg| arg.split_once('='))
+            else {
+                continue;
+            };
+            if KEYS.contains(&key) {
+                if let Err(err) = config.set(key, value) {
+                    errors.push(format!("--{key}: {err}"));
+                }
+            }
+        }
+        if config.find_palette().is_none() {
+            errors.push(format!("palette: unknown palette `{}`", config.palette));
+            config.palette = Self::default().palette;
+        }
+        (config, errors)
     }
 
-    pub fn reload(self) -> Result<Self, Box<dyn Error>> {
-        let defaults = HashMap::from([
-            ("title", "FLOATING"),
-            ("sleep_ms_per_frame", "5"),
-            ("window_width", "640"),
-            ("window_height", "480"),
-            ("grid_width", "8"),
-            ("grid_height", "16"),
-        ]);
-        let config_map = fusion(defaults, Some(self.path.clone().unwrap().as_str()));
-        let title = config_map.get("title").unwrap().to_string();
-        let sleep_ms_per_frame = config_map
-            .get("sleep_ms_per_frame")
-            .unwrap()
-            .parse::<u64>()?;
-        let window_width = config_map.get("window_width").unwrap().parse::<u32>()?;
-        let window_height = config_map.get("window_height").unwrap().parse::<u32>()?;
-        let grid_width = config_map.get("grid_width").unwrap().parse::<u32>()?;
-        let grid_height = config_map.get("grid_height").unwrap().parse::<u32>()?;
-        Ok(Self {
-            path: self.path,
-            title,
-            sleep_ms_per_frame,
-            window_height,
-            window_width,
-            grid_width,
-            grid_height,
-        })
+    pub fn palette(&self) -> Palette {
+        self.find_palette()
+            .unwrap_or_else(|| Palette::builtin("vga").unwrap())
     }
-}
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            path: None,
-            title: "FLOATING".to_string(),
-            sleep_ms_per_frame: 5,
-            window_width: 640,
-            window_height: 640,
-            grid_width: 8,
-            grid_height: 16,
+    // Custom palettes shadow the built-in ones.
+    pub fn find_palette(&self) -> Option<Palette> {
+        self.palettes
+            .iter()
+            .rev()
+            .find(|palette| palette.name == self.palette)
+            .cloned()
+            .or_else(|| Palette::builtin(&self.palette))
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "title" => self.title = value.to_string(),
+            "target_fps" => self.target_fps = parse(value)?,
+            "update_hz" => self.update_hz = positive(value)?,
+            "max_updates_per_frame" => self.max_updates_per_frame = positive(value)?,
+            "vsync" => self.vsync = parse(value)?,
+            "window_width" => self.window_width = positive(value)?,
+            "window_height" => self.window_height = positive(value)?,
+            "grid_width" => self.grid_width = positive(value)?,
+            "grid_height" => self.grid_height = positive(value)?,
+            "zoom" => self.zoom = value.parse()?,
+            "autosave_path" => self.autosave_path = value.to_string(),
+            "autosave_seconds" => self.autosave_seconds = positive(value)?,
+            "screenshot_folder" => self.screenshot_folder = value.to_string(),
+            "palette" => self.palette = value.to_string(),
+            "background" => {
+                self.background = palette::parse_hex(value)
+                    .ok_or_else(|| format!("`{value}` is not a hex colour like 4d4d4d"))?;
+            }
+            "cursor_style" => self.cursor_style = value.parse()?,
+            "cursor_blink_ms" => self.cursor_blink_ms = parse(value)?,
+            _ => return Err(format!("unknown key `{key}`")),
         }
+        Ok(())
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|err| format!("`{value}` is not valid: {err}"))
+}
+
+fn positive<T: FromStr + Default + PartialEq>(value: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    let number = parse(value)?;
+    if number == T::default() {
+        return Err("must be greater than 0".to_string());
+    }
+    Ok(number)
+}
+
+fn suggestion(key: &str) -> String {
+    let common_prefix = |known: &str| {
+        key.chars()
+            .zip(known.chars())
+            .take_while(|(a, b)| a == b)
+            .count()
+    };
+    KEYS.iter()
+        .map(|known| (common_prefix(known), known))
+        .filter(|(length, _)| *length >= 4)
+        .max_by_key(|(length, _)| *length)
+        .map_or_else(String::new, |(_, known)| {
+            format!(", did you mean `{known}`?")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_text(text: &str, args: &[&str]) -> (Config, Vec<String>) {
+        let args = args.iter().map(ToString::to_string);
+        Config::from_text("config.txt", text, args)
+    }
+
+    #[test]
+    fn reads_values_palettes_and_bindings() {
+        let text = [
+            "# comment",
+            "",
+            "target_fps = 30",
+            "cursor_style=bar",
+            "palette.mine=000000,ffffff",
+            "palette=mine",
+            "bind.wall.Ctrl+S=save wall.json",
+        ];
+        let (config, errors) = from_text(&text.join("\n"), &[]);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(config.target_fps, 30);
+        assert_eq!(config.cursor_style, CursorStyle::Bar);
+        assert_eq!(config.palette().colors, vec![0, 0x00ff_ffff]);
+        assert_ne!(config.keymap, Keymap::default());
+    }
+
+    #[test]
+    fn arguments_override_the_file() {
+        let args = ["--target_fps=120", "--export=out.html", "--headless"];
+        let (config, errors) = from_text("target_fps=30\n", &args);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(config.target_fps, 120);
+    }
+
+    #[test]
+    fn bad_lines_keep_defaults() {
+        let text = [
+            "update_hz=0",
+            "vsync=maybe",
+            "no equals sign",
+            "palette=missing",
+            "bind.nowhere.F1=quit",
+        ];
+        let (config, errors) = from_text(&text.join("\n"), &["--window_width=wide"]);
+        let defaults = Config::default();
+        assert_eq!(config.update_hz, defaults.update_hz);
+        assert_eq!(config.vsync, defaults.vsync);
+        assert_eq!(config.window_width, defaults.window_width);
+        assert_eq!(config.palette, defaults.palette);
+        assert_eq!(errors.len(), 6, "{errors:?}");
+        assert!(errors[0].starts_with("config.txt:1: update_hz"));
+    }
+
+    #[test]
+    fn unknown_keys_are_warnings_with_suggestions() {
+        let (config, errors) = from_text("target_fsp=30\n", &[]);
+        assert!(errors.is_empty());
+        assert_eq!(
+            config.warnings,
+            ["config.txt:1: unknown key `target_fsp`, did you mean `target_fps`?"]
+        );
     }
 }
@@ -0,0 +1,579 @@
+use glam::{IVec2, UVec2, Vec2};
+use speedy2d::window::{MouseButton, VirtualKeyCode};
+
+use std::str::FromStr;
+
+use crate::app::{Keyboard, Mouse};
+use crate::cp437::from_unicode;
+use crate::grid::{Grid, Tile};
+use crate::palette::Palette;
+
+const GLYPHS: u32 = 256;
+const MAX_GLYPH_COLUMNS: u32 = 64;
+const SWATCH_WIDTH: u32 = 2;
+const SWATCHES: usize = 16;
+// Palette entries for the panel text and background, swapped for the current tool and glyph.
+const PANEL_FG: u8 = 15;
+const PANEL_BG: u8 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    Pencil,
+    Line,
+    Rectangle,
+    Fill,
+    Text,
+    Picker,
+}
+
+impl Tool {
+    const ALL: [Self; 6] = [
+        Self::Pencil,
+        Self::Line,
+        Self::Rectangle,
+        Self::Fill,
+        Self::Text,
+        Self::Picker,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Pencil => "pencil",
+            Self::Line => "line",
+            Self::Rectangle => "rect",
+            Self::Fill => "fill",
+            Self::Text => "text",
+            Self::Picker => "pick",
+        }
+    }
+
+    fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|tool| *tool == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+}
+
+// The toolbar names, `tool <name>` in the command language.
+impl FromStr for Tool {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|tool| tool.name() == text)
+            .ok_or_else(|| {
+                format!("unknown tool `{text}`, tools are pencil, line, rect, fill, text and pick")
+            })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Layout {
+    cell_size: UVec2,
+    columns: u32,
+    rows: u32,
+    // Grid cell shown in the top left corner.
+    offset: UVec2,
+    // Pixels left over by whole cells are split evenly around them.
+    origin: UVec2,
+}
+
+impl Layout {
+    pub fn new(viewport_size: UVec2, cell_size: UVec2, offset: UVec2) -> Self {
+        let cell_size = cell_size.max(UVec2::ONE);
+        Self {
+            cell_size,
+            columns: viewport_size.x / cell_size.x,
+            rows: viewport_size.y / cell_size.y,
+            offset,
+            origin: viewport_size % cell_size / 2,
+        }
+    }
+
+    // The part of the window from screen cell `min`, `size` cells large, with its own cell 0,0.
+    pub fn region(self, min: UVec2, size: UVec2) -> Self {
+        let size = size.min(self.view() - min.min(self.view()));
+        Self {
+            columns: size.x,
+            rows: size.y,
+            origin: self.origin + min * self.cell_size,
+            ..self
+        }
+    }
+
+    pub const fn cell_size(&self) -> UVec2 {
+        self.cell_size
+    }
+
+    // Top left pixel of a screen cell.
+    pub fn position(&self, cell: UVec2) -> Vec2 {
+        (self.origin + cell * self.cell_size).as_vec2()
+    }
+
+    // Size of the window in cells.
+    pub const fn view(&self) -> UVec2 {
+        UVec2::new(self.columns, self.rows)
+    }
+
+    pub fn to_grid(&self, cell: UVec2) -> UVec2 {
+        cell + self.offset
+    }
+
+    pub fn to_screen(&self, cell: UVec2) -> Option<UVec2> {
+        let screen = cell.as_ivec2() - self.offset.as_ivec2();
+        (screen.cmpge(IVec2::ZERO).all() && screen.cmplt(self.view().as_ivec2()).all())
+            .then(|| screen.as_uvec2())
+    }
+
+    pub fn cell_at(&self, position: Vec2) -> Option<UVec2> {
+        let position = position - self.origin.as_vec2();
+        if position.x < 0.0 || position.y < 0.0 {
+            return None;
+        }
+        let cell = UVec2::new(
+            position.x as u32 / self.cell_size.x,
+            position.y as u32 / self.cell_size.y,
+        );
+        (cell.x < self.columns && cell.y < self.rows).then_some(cell)
+    }
+
+    fn glyph_columns(&self) -> u32 {
+        self.columns.clamp(1, MAX_GLYPH_COLUMNS)
+    }
+
+    fn glyph_rows(&self) -> u32 {
+        GLYPHS.div_ceil(self.glyph_columns())
+    }
+
+    fn toolbar_row(&self) -> u32 {
+        self.rows.saturating_sub(2 + self.glyph_rows())
+    }
+
+    fn colors_row(&self) -> u32 {
+        self.toolbar_row() + 1
+    }
+
+    fn glyphs_row(&self) -> u32 {
+        self.toolbar_row() + 2
+    }
+
+    fn hit(&self, cell: UVec2) -> Option<PanelHit> {
+        if cell.y < self.toolbar_row() {
+            return None;
+        }
+        if cell.y == self.toolbar_row() {
+            let mut start = 0;
+            for tool in Tool::ALL {
+                let end = start + tool.name().len() as u32 + 2;
+                if (start..end).contains(&cell.x) {
+                    return Some(PanelHit::Tool(tool));
+                }
+                start = end;
+            }
+        } else if cell.y == self.colors_row() {
+            let idx = (cell.x / SWATCH_WIDTH) as usize;
+            if idx < SWATCHES {
+                return Some(PanelHit::Color(idx));
+            }
+        } else if cell.x < self.glyph_columns() {
+            let idx = (cell.y - self.glyphs_row()) * self.glyph_columns() + cell.x;
+            if idx < GLYPHS {
+                return Some(PanelHit::Glyph(char::from(idx as u8)));
+            }
+        }
+        Some(PanelHit::Nothing)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum PanelHit {
+    Tool(Tool),
+    Color(usize),
+    Glyph(char),
+    Nothing,
+}
+
+pub struct Editor {
+    pub enabled: bool,
+    tool: Tool,
+    glyph: char,
+    // Only the colours are used, they may be palette references.
+    colors: Tile,
+
+    hovered: Option<UVec2>,
+    drag_start: Option<UVec2>,
+    drag_end: Option<UVec2>,
+    last_painted: Option<UVec2>,
+    caret: UVec2,
+    caret_line_start: u32,
+    filled: bool,
+
+    left_was_pressed: bool,
+    right_was_pressed: bool,
+}
+
+impl Editor {
+    pub const fn new() -> Self {
+        Self {
+            enabled: false,
+            tool: Tool::Pencil,
+            glyph: '\u{db}',
+            colors: Tile::new(' '),
+
+            hovered: None,
+            drag_start: None,
+            drag_end: None,
+            last_painted: None,
+            caret: UVec2::ZERO,
+            caret_line_start: 0,
+            filled: false,
+
+            left_was_pressed: false,
+            right_was_pressed: false,
+        }
+    }
+
+    // Tool hotkeys are `editor` bindings in the keymap.
+    pub fn select_tool(&mut self, tool: Option<Tool>) {
+        self.tool = tool.unwrap_or_else(|| self.tool.next());
+    }
+
+    pub fn wants_text_input(&self) -> bool {
+        self.enabled && self.tool == Tool::Text
+    }
+
+    pub fn input(
+        &mut self,
+        grid: &mut Grid,
+        palette: &Palette,
+        layout: Layout,
+        mouse: &Mouse,
+        keyboard: &Keyboard,
+    ) {
+        let left = mouse.is_pressed(MouseButton::Left);
+        let right = mouse.is_pressed(MouseButton::Right);
+        let just_left = left && !self.left_was_pressed;
+        let just_right = right && !self.right_was_pressed;
+        let released_left = !left && self.left_was_pressed;
+        self.left_was_pressed = left;
+        self.right_was_pressed = right;
+        self.filled = keyboard.modifiers.shift();
+
+        self.handle_keys(grid, keyboard);
+
+        self.hovered = layout.cell_at(mouse.position());
+        let hit = self.hovered.and_then(|cell| layout.hit(cell));
+        if let Some(hit) = hit {
+            if just_left || just_right {
+                self.click_panel(hit, just_right, palette);
+            }
+        }
+        let cell = self
+            .hovered
+            .filter(|_| hit.is_none())
+            .map(|cell| layout.to_grid(cell));
+
+        if released_left {
+            self.finish_drag(grid);
+        }
+        let Some(cell) = cell else {
+            self.last_painted = None;
+            return;
+        };
+
+        if just_right {
+            self.pick(grid, cell);
+            return;
+        }
+        match self.tool {
+            Tool::Pencil if left => {
+                let from = self.last_painted.unwrap_or(cell);
+                for cell in line(from, cell) {
+                    self.paint(grid, cell);
+                }
+                self.last_painted = Some(cell);
+            }
+            Tool::Line | Tool::Rectangle if just_left => {
+                self.drag_start = Some(cell);
+                self.drag_end = Some(cell);
+            }
+            Tool::Line | Tool::Rectangle if left => self.drag_end = Some(cell),
+            Tool::Fill if just_left => self.flood_fill(grid, cell),
+            Tool::Text if just_left => {
+                self.caret = cell;
+                self.caret_line_start = cell.x;
+            }
+            Tool::Picker if just_left => self.pick(grid, cell),
+            _ => self.last_painted = None,
+        }
+    }
+
+    fn handle_keys(&mut self, grid: &mut Grid, keyboard: &Keyboard) {
+        if self.tool != Tool::Text {
+            return;
+        }
+
+        for key in &keyboard.just_pressed {
+            match key {
+                VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                    self.caret = UVec2::new(self.caret_line_start, self.caret.y + 1);
+                }
+                VirtualKeyCode::Backspace => {
+                    self.caret.x = self.caret.x.saturating_sub(1);
+                    grid.set(self.caret, self.with_colors(' '));
+                }
+                VirtualKeyCode::Left => self.caret.x = self.caret.x.saturating_sub(1),
+                VirtualKeyCode::Right => self.caret.x += 1,
+                VirtualKeyCode::Up => self.caret.y = self.caret.y.saturating_sub(1),
+                VirtualKeyCode::Down => self.caret.y += 1,
+                _ => (),
+            }
+        }
+        for ch in keyboard.buffer.iter().filter(|ch| !ch.is_control()) {
+            let glyph = from_unicode(*ch);
+            grid.set(self.caret, self.with_colors(glyph));
+            self.caret.x += 1;
+        }
+    }
+
+    fn click_panel(&mut self, hit: PanelHit, secondary: bool, palette: &Palette) {
+        match hit {
+            PanelHit::Tool(tool) => self.tool = tool,
+            PanelHit::Color(idx) if secondary => {
+                self.colors = self.colors.with_bg_index(idx as u8, palette);
+            }
+            PanelHit::Color(idx) => self.colors = self.colors.with_fg_index(idx as u8, palette),
+            PanelHit::Glyph(glyph) => self.glyph = glyph,
+            PanelHit::Nothing => (),
+        }
+    }
+
+    fn finish_drag(&mut self, grid: &mut Grid) {
+        if let (Some(start), Some(end)) = (self.drag_start.take(), self.drag_end.take()) {
+            for cell in self.shape(start, end) {
+                self.paint(grid, cell);
+            }
+        }
+        self.last_painted = None;
+    }
+
+    fn shape(&self, start: UVec2, end: UVec2) -> Vec<UVec2> {
+        match self.tool {
+            Tool::Line => line(start, end),
+            Tool::Rectangle => rectangle(start, end, self.filled),
+            _ => Vec::new(),
+        }
+    }
+
+    const fn with_colors(&self, ch: char) -> Tile {
+        Tile { ch, ..self.colors }
+    }
+
+    const fn brush(&self) -> Tile {
+        self.with_colors(self.glyph)
+    }
+
+    fn paint(&self, grid: &mut Grid, cell: UVec2) {
+        grid.set(cell, self.brush());
+    }
+
+    fn pick(&mut self, grid: &Grid, cell: UVec2) {
+        if let Some(tile) = grid.get(cell) {
+            self.glyph = tile.ch;
+            self.colors = *tile;
+        }
+    }
+
+    fn flood_fill(&self, grid: &mut Grid, cell: UVec2) {
+        let Some(target) = grid.get(cell).copied() else {
+            return;
+        };
+        let brush = self.brush();
+        if target == brush {
+            return;
+        }
+        let mut stack = vec![cell];
+        while let Some(cell) = stack.pop() {
+            match grid.get_mut(cell) {
+                Some(tile) if *tile == target => *tile = brush,
+                _ => continue,
+            }
+            stack.push(cell + UVec2::X);
+            stack.push(cell + UVec2::Y);
+            if cell.x > 0 {
+                stack.push(cell - UVec2::X);
+            }
+            if cell.y > 0 {
+                stack.push(cell - UVec2::Y);
+            }
+        }
+    }
+
+    pub fn overlay(&self, layout: Layout, palette: &Palette) -> Vec<(UVec2, Tile)> {
+        let mut tiles = Vec::new();
+        if !self.enabled {
+            return tiles;
+        }
+
+        if let (Some(start), Some(end)) = (self.drag_start, self.drag_end) {
+            tiles.extend(
+                self.shape(start, end)
+                    .into_iter()
+                    .filter_map(|cell| layout.to_screen(cell))
+                    .map(|cell| (cell, self.brush())),
+            );
+        } else if let Some(cell) = self.hovered.filter(|cell| layout.hit(*cell).is_none()) {
+            if self.tool != Tool::Text {
+                tiles.push((cell, self.brush()));
+            }
+        }
+        if self.tool == Tool::Text {
+            if let Some(caret) = layout.to_screen(self.caret) {
+                tiles.push((caret, self.with_colors('_')));
+            }
+        }
+
+        let panel = Tile::new(' ')
+            .with_fg_index(PANEL_FG, palette)
+            .with_bg_index(PANEL_BG, palette);
+        let selected = panel
+            .with_fg_index(PANEL_BG, palette)
+            .with_bg_index(PANEL_FG, palette);
+        for y in layout.toolbar_row()..layout.rows {
+            for x in 0..layout.columns {
+                tiles.push((UVec2::new(x, y), panel));
+            }
+        }
+
+        let mut x = 0;
+        for tool in Tool::ALL {
+            let label = format!(" {} ", tool.name());
+            let tile = if tool == self.tool { selected } else { panel };
+            for ch in label.chars() {
+                tiles.push((UVec2::new(x, layout.toolbar_row()), Tile { ch, ..tile }));
+                x += 1;
+            }
+        }
+        let brush_at = UVec2::new(x + 1, layout.toolbar_row());
+        tiles.push((brush_at, self.brush()));
+
+        for idx in 0..SWATCHES {
+            let swatch = Tile::new(' ').with_bg_index(idx as u8, palette);
+            for offset in 0..SWATCH_WIDTH {
+                let marker = match offset {
+                    0 if swatch.bg == self.colors.fg => 'F',
+                    1 if swatch.bg == self.colors.bg => 'B',
+                    _ => ' ',
+                };
+                let contrast = if idx < 8 { PANEL_FG } else { PANEL_BG };
+                let cell = UVec2::new(idx as u32 * SWATCH_WIDTH + offset, layout.colors_row());
+                let tile = swatch.with_fg_index(contrast, palette);
+                tiles.push((cell, Tile { ch: marker, ..tile }));
+            }
+        }
+
+        for idx in 0..GLYPHS {
+            let glyph = char::from(idx as u8);
+            let cell = UVec2::new(
+                idx % layout.glyph_columns(),
+                layout.glyphs_row() + idx / layout.glyph_columns(),
+            );
+            let tile = if glyph == self.glyph {
+                selected
+            } else {
+                panel.with_fg(self.colors.fg)
+            };
+            tiles.push((cell, Tile { ch: glyph, ..tile }));
+        }
+        tiles
+    }
+}
+
+fn line(from: UVec2, to: UVec2) -> Vec<UVec2> {
+    let (from, to) = (from.as_ivec2(), to.as_ivec2());
+    let delta = IVec2::new((to.x - from.x).abs(), -(to.y - from.y).abs());
+    let step = IVec2::new((to.x - from.x).signum(), (to.y - from.y).signum());
+    let mut error = delta.x + delta.y;
+    let mut current = from;
+    let mut cells = vec![current.as_uvec2()];
+    while current != to {
+        let doubled = 2 * error;
+        if doubled >= delta.y {
+            error += delta.y;
+            current.x += step.x;
+        }
+        if doubled <= delta.x {
+            error += delta.x;
+            current.y += step.y;
+        }
+        cells.push(current.as_uvec2());
+    }
+    cells
+}
+
+fn rectangle(a: UVec2, b: UVec2, filled: bool) -> Vec<UVec2> {
+    let (min, max) = (a.min(b), a.max(b));
+    let mut cells = Vec::new();
+    for y in min.y..=max.y {
+        for x in min.x..=max.x {
+            if filled || x == min.x || x == max.x || y == min.y || y == max.y {
+                cells.push(UVec2::new(x, y));
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(points: &[(u32, u32)]) -> Vec<UVec2> {
+        points.iter().map(|&(x, y)| UVec2::new(x, y)).collect()
+    }
+
+    #[test]
+    fn lines_step_one_cell_at_a_time() {
+        let diagonal = line(UVec2::new(3, 2), UVec2::new(0, 0));
+        assert_eq!(diagonal, cells(&[(3, 2), (2, 1), (1, 1), (0, 0)]));
+        assert_eq!(line(UVec2::ONE, UVec2::ONE), cells(&[(1, 1)]));
+        let tall = line(UVec2::new(0, 0), UVec2::new(1, 4));
+        assert_eq!(tall.len(), 5);
+        for pair in tall.windows(2) {
+            let step = pair[1].as_ivec2() - pair[0].as_ivec2();
+            assert!(step.abs().max_element() == 1 && step.y == 1);
+        }
+    }
+
+    #[test]
+    fn rectangles_are_outlined_or_filled() {
+        let outline = rectangle(UVec2::new(2, 2), UVec2::new(0, 0), false);
+        assert_eq!(outline.len(), 8);
+        assert!(!outline.contains(&UVec2::ONE));
+        let filled = rectangle(UVec2::new(0, 0), UVec2::new(2, 1), true);
+        let expected = cells(&[(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]);
+        assert_eq!(filled, expected);
+    }
+
+    #[test]
+    fn flood_fill_stops_at_other_tiles() {
+        // A wall down column 2 keeps the fill on the left.
+        let mut grid = Grid::new(5, 3);
+        for y in 0..3 {
+            grid.set(UVec2::new(2, y), Tile::new('#'));
+        }
+        let mut editor = Editor::new();
+        editor.glyph = 'x';
+        editor.flood_fill(&mut grid, UVec2::new(0, 1));
+
+        let rows: Vec<String> = grid
+            .rows()
+            .map(|row| row.iter().map(|tile| tile.ch).collect())
+            .collect();
+        assert_eq!(rows, ["xx#  ", "xx#  ", "xx#  "]);
+        // Filling with the same brush again changes nothing.
+        let before = grid.tiles().to_vec();
+        editor.flood_fill(&mut grid, UVec2::ZERO);
+        assert_eq!(grid.tiles(), before);
+    }
+}
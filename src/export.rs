@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use speedy2d::color::Color;
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::cp437::to_unicode;
+use crate::grid::{color_to_rgba, Attributes, Grid, Tile};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextFormat {
+    Plain,
+    Ansi,
+    Html,
+}
+
+impl TextFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "txt" => Some(Self::Plain),
+            "ansi" => Some(Self::Ansi),
+            "html" | "htm" => Some(Self::Html),
+            _ => None,
+        }
+    }
+}
+
+pub fn render(grid: &Grid, format: TextFormat, title: &str) -> String {
+    match format {
+        TextFormat::Plain => plain(grid),
+        TextFormat::Ansi => ansi(grid),
+        TextFormat::Html => html(grid, title),
+    }
+}
+
+// Rows up to the last one with a written cell, blank cells may be in any palette's colours.
+fn used_rows(grid: &Grid) -> impl Iterator<Item = &[Tile]> {
+    let used = grid
+        .rows()
+        .rposition(|row| !row.iter().all(Tile::is_blank))
+        .map_or(0, |idx| idx + 1);
+    grid.rows().take(used)
+}
+
+pub fn plain(grid: &Grid) -> String {
+    let mut text = String::new();
+    for row in used_rows(grid) {
+        let line: String = row.iter().map(|tile| to_unicode(tile.ch)).collect();
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+pub fn ansi(grid: &Grid) -> String {
+    let mut text = String::new();
+    for row in used_rows(grid) {
+        let used = row
+            .iter()
+            .rposition(|tile| !tile.is_blank())
+            .map_or(0, |idx| idx + 1);
+        let mut current = None;
+        for tile in &row[..used] {
+            let style = (
+                color_to_rgba(tile.fg),
+                color_to_rgba(tile.bg),
+                tile.attributes,
+            );
+            if current != Some(style) {
+                text.push_str(&sgr(tile));
+                current = Some(style);
+            }
+            text.push(to_unicode(tile.ch));
+        }
+        text.push_str("\x1b[0m\n");
+    }
+    text
+}
+
+// Select graphic rendition sequence that sets the colors and attributes of `tile`.
+pub fn sgr(tile: &Tile) -> String {
+    let [fr, fg, fb, _] = color_to_rgba(tile.fg);
+    let [br, bg, bb, _] = color_to_rgba(tile.bg);
+    let mut text = String::from("\x1b[0");
+    for (attribute, code) in [
+        (Attributes::BOLD, 1),
+        (Attributes::UNDERLINE, 4),
+        (Attributes::BLINK, 5),
+        (Attributes::REVERSE, 7),
+    ] {
+        if tile.attributes.contains(attribute) {
+            let _ = write!(text, ";{code}");
+        }
+    }
+    let _ = write!(text, ";38;2;{fr};{fg};{fb};48;2;{br};{bg};{bb}m");
+    text
+}
+
+pub fn html(grid: &Grid, title: &str) -> String {
+    let mut body = String::new();
+    for row in used_rows(grid) {
+        let mut run = String::new();
+        let mut current: Option<(Color, Color, bool)> = None;
+        for tile in row {
+            let (fg, bg) = tile.shown_colors(true);
+            let style = (fg, bg, tile.attributes.contains(Attributes::UNDERLINE));
+            if current != Some(style) {
+                if let Some(style) = current {
+                    push_span(&mut body, &run, style);
+                    run.clear();
+                }
+                current = Some(style);
+            }
+            match to_unicode(tile.ch) {
+                '&' => run.push_str("&amp;"),
+                '<' => run.push_str("&lt;"),
+                '>' => run.push_str("&gt;"),
+                ch => run.push(ch),
+            }
+        }
+        if let Some(style) = current {
+            push_span(&mut body, &run, style);
+        }
+        body.push('\n');
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n\
+         <body style=\"margin:0;background:#4d4d4d\">\n\
+         <pre style=\"margin:0;font-family:monospace;line-height:1\">\n{body}</pre>\n</body>\n</html>\n",
+        title.replace('&', "&amp;").replace('<', "&lt;")
+    )
+}
+
+fn push_span(body: &mut String, run: &str, (fg, bg, underline): (Color, Color, bool)) {
+    let decoration = if underline {
+        ";text-decoration:underline"
+    } else {
+        ""
+    };
+    let _ = write!(
+        body,
+        "<span style=\"color:{};background:{}{decoration}\">{run}</span>",
+        hex(fg),
+        hex(bg)
+    );
+}
+
+fn hex(color: Color) -> String {
+    let [r, g, b, _] = color_to_rgba(color);
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+    use glam::UVec2;
+
+    // Blank cells in solarized colours, "Hi" on the second row and a coloured space after it.
+    fn grid(palette: &Palette) -> Grid {
+        let mut grid = Grid::new(6, 4);
+        grid.clear(palette);
+        let text = Tile::blank(palette).with_fg_index(1, palette);
+        grid.set(UVec2::new(0, 1), Tile { ch: 'H', ..text });
+        grid.set(UVec2::new(1, 1), Tile { ch: 'i', ..text });
+        let red = Tile::blank(palette).with_bg_index(1, palette);
+        grid.set(UVec2::new(4, 1), red);
+        grid
+    }
+
+    #[test]
+    fn plain_text_drops_blank_rows_and_trailing_spaces() {
+        let solarized = Palette::builtin("solarized").unwrap();
+        assert_eq!(plain(&grid(&solarized)), "\nHi\n");
+        assert_eq!(plain(&Grid::new(3, 2)), "");
+    }
+
+    #[test]
+    fn ansi_keeps_coloured_cells_and_trims_blanks() {
+        let solarized = Palette::builtin("solarized").unwrap();
+        let blank = Tile::blank(&solarized);
+        let text = blank.with_fg_index(1, &solarized);
+        let red = blank.with_bg_index(1, &solarized);
+        let row = format!("{}Hi{}  {} ", sgr(&text), sgr(&blank), sgr(&red));
+        assert_eq!(ansi(&grid(&solarized)), format!("\x1b[0m\n{row}\x1b[0m\n"));
+        assert_eq!(ansi(&Grid::new(3, 2)), "");
+    }
+}
@@ -1,29 +1,55 @@
-pub fn to_rgba_bytes(vga8: &[[u8; 16]; 256]) -> Vec<u8> {
+pub fn to_rgba_bytes<'a>(lines: impl Iterator<Item = &'a u8>) -> Vec<u8> {
     let mut expanded: Vec<u8> = Vec::with_capacity(4 * 8 * 16 * 256);
-    for character in vga8 {
-        for line in character {
-            for i in 0..8 {
-                let mask = 1 << i;
-                let bit_is_set = (mask & line) > 0;
-                if bit_is_set {
-                    expanded.push(255);
-                    expanded.push(255);
-                    expanded.push(255);
-                    expanded.push(255);
-                } else {
-                    expanded.push(0);
-                    expanded.push(0);
-                    expanded.push(0);
-                    expanded.push(0);
-                }
+    for line in lines {
+        for i in 0..8 {
+            let mask = 1 << i;
+            let bit_is_set = (mask & line) > 0;
+            if bit_is_set {
+                expanded.push(255);
+                expanded.push(255);
+                expanded.push(255);
+                expanded.push(255);
+            } else {
+                expanded.push(0);
+                expanded.push(0);
+                expanded.push(0);
+                expanded.push(0);
             }
         }
     }
     expanded
 }
 
-pub fn vga8() -> Vec<u8> {
-    to_rgba_bytes(&VGA8)
+// 8 pixel wide bitmap font, one byte per glyph line with the leftmost pixel in the lowest bit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Font {
+    pub height: u32,
+    pub lines: Vec<u8>,
+}
+
+impl Font {
+    pub fn vga8() -> Self {
+        Self {
+            height: 16,
+            lines: VGA8.iter().flatten().copied().collect(),
+        }
+    }
+
+    pub fn glyph_count(&self) -> u32 {
+        self.lines.len() as u32 / self.height.max(1)
+    }
+
+    pub fn glyph(&self, idx: u32) -> &[u8] {
+        let height = self.height as usize;
+        let idx = (idx % self.glyph_count().max(1)) as usize;
+        self.lines
+            .get(idx * height..(idx + 1) * height)
+            .unwrap_or(&[])
+    }
+
+    pub fn to_rgba_bytes(&self) -> Vec<u8> {
+        to_rgba_bytes(self.lines.iter())
+    }
 }
 
 #[allow(clippy::unreadable_literal)]
@@ -6,23 +6,81 @@ use speedy2d::image::ImageSmoothingMode;
 use speedy2d::Graphics2D;
 
 use glam::{UVec2, Vec2};
+use image::RgbaImage;
 
+use speedy2d::window::MouseButton;
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::ansi::{self, Sauce};
 use crate::app::{Keyboard, Mouse};
+use crate::camera::{Camera, Zoom, MAX_ZOOM};
+use crate::cast;
 use crate::config::Config;
-use crate::font::vga8;
+use crate::cp437::from_unicode;
+use crate::editor::{Editor, Layout, Tool};
+use crate::export::{self, TextFormat};
+use crate::font::Font;
+use crate::grid::{rgba, Attributes, Grid, Tile};
+use crate::layer::{self, Layers};
+use crate::palette::{self, Palette};
+use crate::pane::{self, Panes, Placement};
+use crate::pointer::{CellEvent, Pointer};
+use crate::raster::{self, Canvas};
+use crate::save::{self, Document};
+use crate::screenshot::{self, Region};
 use crate::spritesheet::Spritesheet;
+use crate::widget::{self, Ui, UiChange};
+use crate::xp;
 
 const FILE: &'static str = include_str!("./game.rs");
 
+pub const BUFFER_WIDTH: u32 = 80;
+pub const BUFFER_HEIGHT: u32 = 40;
+const STATUS_TIME: Duration = Duration::from_secs(5);
+const BLINK_TIME: Duration = Duration::from_millis(500);
+
 pub struct Game {
     config: Config,
     images: Vec<ImageHandle>,
     spritesheets: Vec<Spritesheet>,
-    counter: usize,
-    display_buffer: [[Tile; 80]; 40],
+    font: Font,
+    font_changed: bool,
+    palette: Palette,
+    // Game time, advanced by `update`.
+    elapsed: Duration,
+    display_buffer: Grid,
+    layers: Layers,
+    panes: Panes,
+    ui: Ui,
+    pointer: Pointer,
+    camera: Camera,
+    zoom: Zoom,
+    scale_factor: f64,
 
     cursor: Cursor,
+    cursor_style: CursorStyle,
+    cursor_blink: Duration,
+    // Blinking restarts whenever the cursor moves, so it is visible right after.
+    cursor_moved: Duration,
     commands: Vec<Command>,
+    editor: Editor,
+    show_stats: bool,
+    quitting: bool,
+    autosave: Option<Autosave>,
+    sauce: Option<Sauce>,
+    status: Option<(String, Duration)>,
+    screenshots: Vec<screenshot::Request>,
+    recordings: Vec<RecordRequest>,
+    cast: Option<cast::Recorder>,
+    player: Option<cast::Player>,
+    // Relative paths in file commands start here, the working directory when empty.
+    directory: PathBuf,
 
     viewport_size: UVec2,
 }
@@ -30,106 +88,736 @@ pub struct Game {
 impl Game {
     pub fn new(config: Config) -> Self {
         let viewport_size = UVec2::new(config.window_width, config.window_height);
-        let buffer = [[Tile::new(' '); 80]; 40];
-        let cursor = Cursor::new('a', Color::WHITE, Color::BLACK, 0, 0);
+        let palette = config.palette();
+        let mut buffer = Grid::new(BUFFER_WIDTH, BUFFER_HEIGHT);
+        buffer.recolor(&palette);
+        let mut cursor = Cursor::default();
+        cursor.recolor(&palette);
+        let zoom = config.zoom;
+        let (cursor_style, cursor_blink) = (
+            config.cursor_style,
+            Duration::from_millis(config.cursor_blink_ms),
+        );
+        let autosave = (!config.autosave_path.is_empty()).then(|| Autosave {
+            path: PathBuf::from(&config.autosave_path),
+            interval: Duration::from_secs(config.autosave_seconds),
+            last: Instant::now(),
+        });
         Self {
             config,
             images: Vec::new(),
             spritesheets: Vec::new(),
-            counter: 0,
+            font: Font::vga8(),
+            font_changed: true,
+            palette,
+            elapsed: Duration::ZERO,
+            layers: Layers::new(buffer.size()),
+            panes: Panes::new(),
+            ui: Ui::new(),
+            pointer: Pointer::new(),
+            camera: Camera::new(),
+            zoom,
+            scale_factor: 1.0,
             display_buffer: buffer,
 
             cursor,
+            cursor_style,
+            cursor_blink,
+            cursor_moved: Duration::ZERO,
             commands: Vec::new(),
+            editor: Editor::new(),
+            show_stats: false,
+            quitting: false,
+            autosave,
+            sauce: None,
+            status: None,
+            screenshots: Vec::new(),
+            recordings: Vec::new(),
+            cast: None,
+            player: None,
+            directory: PathBuf::new(),
 
             viewport_size,
         }
     }
 
     pub fn setup(&mut self, graphics: &mut Graphics2D) {
-        let image_handle = graphics
-            .create_image_from_raw_pixels(
-                ImageDataType::RGBA,
-                ImageSmoothingMode::NearestNeighbor,
-                UVec2::new(8, 16 * 256),
-                &vga8(),
-            )
-            .unwrap();
-        self.spritesheets
-            .push(Spritesheet::new(image_handle, 1, 256));
+        if !self.config.warnings.is_empty() {
+            self.report(self.config.warnings.join("; "));
+        }
+        if let Err(err) = self.upload_font(graphics) {
+            self.report(format!("{err:#}"));
+        }
+        self.populate();
+    }
 
+    pub fn populate(&mut self) {
         for (y, line) in FILE.lines().enumerate() {
-            self.display_string(
+            self.display_text(
                 &line
                     .chars()
                     .enumerate()
                     .filter_map(|(i, ch)| if i < 40 { Some(ch) } else { None })
                     .collect::<String>(),
                 UVec2::new(0, y as u32),
-                &Color::WHITE,
-                &Color::BLACK,
+                15,
+                0,
             );
         }
+
+        if let Some(path) = self.autosave.as_ref().map(|autosave| autosave.path.clone()) {
+            if path.exists() {
+                self.load(&path);
+            }
+        }
+    }
+
+    // A font the graphics backend rejects is swapped for the VGA one, uploaded next frame.
+    pub fn upload_font(&mut self, graphics: &mut Graphics2D) -> anyhow::Result<()> {
+        if !self.font_changed {
+            return Ok(());
+        }
+        let glyphs = self.font.glyph_count();
+        let image_handle = match graphics.create_image_from_raw_pixels(
+            ImageDataType::RGBA,
+            ImageSmoothingMode::NearestNeighbor,
+            UVec2::new(8, self.font.height * glyphs),
+            &self.font.to_rgba_bytes(),
+        ) {
+            Ok(image_handle) => image_handle,
+            Err(err) => {
+                self.font = Font::vga8();
+                anyhow::bail!("Couldn't upload the font: {err}");
+            }
+        };
+        self.spritesheets.clear();
+        self.spritesheets
+            .push(Spritesheet::new(image_handle, 1, glyphs));
+        self.font_changed = false;
+        Ok(())
     }
 
-    pub fn input(&mut self, viewport_size: UVec2, _mouse: &Mouse, keyboard: &Keyboard) {
+    pub fn input(&mut self, viewport_size: UVec2, mouse: &Mouse, keyboard: &Keyboard) {
         self.viewport_size = viewport_size;
+        if keyboard.modifiers.ctrl() {
+            self.zoom_input(mouse);
+        }
+        let cell = self.cell_at(mouse.position());
+        self.pointer.input(cell, mouse);
+        if !self.editor.enabled {
+            self.ui
+                .input(cell, mouse.is_pressed(MouseButton::Left), keyboard);
+        }
+        let use_arrows = !self.editor.wants_text_input() && !self.ui.wants_keyboard();
+        self.camera
+            .input(mouse, keyboard, self.screen_cell_size(), use_arrows);
+        self.camera
+            .clamp(self.display_buffer.size(), self.layout().view());
+        if self.editor.enabled {
+            let layout = self.layout();
+            self.editor.input(
+                &mut self.display_buffer,
+                &self.palette,
+                layout,
+                mouse,
+                keyboard,
+            );
+        }
     }
 
-    pub fn update(&mut self, current_frame: u64) {
-        self.counter += 1;
+    pub fn wants_text_input(&self) -> bool {
+        self.editor.wants_text_input() || self.ui.wants_keyboard()
+    }
 
-        for command in std::mem::take(&mut self.commands).into_iter() {
-            match command {
-                Command::Write => self.display_cursor(),
-                Command::Up => {
-                    self.cursor.y = if self.cursor.y > 0 {
-                        self.cursor.y - 1
-                    } else {
-                        0
-                    }
+    // Grid cell under a window pixel, through the camera, zoom and main pane.
+    pub fn cell_at(&self, pixel: Vec2) -> Option<UVec2> {
+        let layout = self.layout();
+        let cell = layout.cell_at(pixel)?;
+        let cell = layout.to_grid(cell);
+        self.display_buffer.get(cell).is_some().then_some(cell)
+    }
+
+    // The main pane, where the wall is shown and edited.
+    fn layout(&self) -> Layout {
+        let window = self.window_layout();
+        self.panes
+            .area(pane::MAIN, window.view())
+            .map_or(window, |area| window.region(area.min, area.size))
+    }
+
+    // Window pixels showing `region` of the wall, the top left one and the size. Only the part
+    // in the main pane counts, `None` if none of it is shown.
+    pub fn screen_area(&self, region: Region) -> Option<(UVec2, UVec2)> {
+        let layout = self.layout();
+        let min = UVec2::new(region.x, region.y);
+        let max = UVec2::new(
+            region.x.saturating_add(region.width),
+            region.y.saturating_add(region.height),
+        );
+        let min = min.max(layout.to_grid(UVec2::ZERO));
+        let max = max
+            .min(layout.to_grid(layout.view()))
+            .min(self.display_buffer.size());
+        if min.cmpge(max).any() {
+            return None;
+        }
+        let screen = layout.to_screen(min)?;
+        Some((
+            layout.position(screen).as_uvec2(),
+            (max - min) * layout.cell_size(),
+        ))
+    }
+
+    // The whole window, for the status line and overlays.
+    fn window_layout(&self) -> Layout {
+        Layout::new(
+            self.viewport_size,
+            self.screen_cell_size(),
+            self.camera.offset,
+        )
+    }
+
+    // Ctrl+wheel steps the zoom, the Ctrl+plus/minus/0 keys are in the keymap.
+    fn zoom_input(&mut self, mouse: &Mouse) {
+        // `signum` of 0.0 is 1.0, so frames without wheel movement have to be skipped.
+        let lines = mouse.scroll_lines();
+        if lines == 0.0 {
+            return;
+        }
+        self.zoom_by(lines.signum() as i32);
+    }
+
+    fn zoom_by(&mut self, steps: i32) {
+        let level = self.zoom_level().saturating_add_signed(steps);
+        self.zoom = Zoom::Level(level.clamp(1, MAX_ZOOM));
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+
+    pub fn zoom_level(&self) -> u32 {
+        match self.zoom {
+            Zoom::Level(level) => level,
+            Zoom::Auto => {
+                let wall = self.display_buffer.size().max(UVec2::ONE) * self.cell_size();
+                let fit = (self.viewport_size / wall).min_element();
+                let display = self.scale_factor.floor() as u32;
+                fit.max(display).clamp(1, MAX_ZOOM)
+            }
+        }
+    }
+
+    // Cell size on screen, whole multiples of the font size only.
+    pub fn screen_cell_size(&self) -> UVec2 {
+        self.cell_size() * self.zoom_level()
+    }
+
+    fn run_command(&mut self, command: Command) {
+        match command {
+            Command::Write => self.display_cursor(),
+            Command::ToggleEditor => self.editor.enabled = !self.editor.enabled,
+            Command::Tool(tool) => self.editor.select_tool(tool),
+            Command::Quit => self.quitting = true,
+            Command::ToggleStats => self.show_stats = !self.show_stats,
+            Command::Layer(name, action) => {
+                self.change_layer(&name, action);
+            }
+            Command::Cursor(change) => self.change_cursor(change),
+            Command::Zoom(zoom) => self.zoom = zoom,
+            Command::ZoomBy(steps) => self.zoom_by(steps),
+            Command::PaneText(id, text) => match self.panes.get_mut(id) {
+                Some(pane) => pane.write_line(&text),
+                None => self.report(format!("No pane {id}")),
+            },
+            Command::PaneClear(id) => match self.panes.get_mut(id) {
+                Some(pane) => pane.clear(),
+                None => self.report(format!("No pane {id}")),
+            },
+            Command::MouseEcho(echo) => self.pointer.echo = echo,
+            Command::Ui(change) => {
+                if !self.ui.apply(change) {
+                    self.report(widget::USAGE);
                 }
-                Command::Down => {
-                    self.cursor.y = if self.cursor.y < 40 {
-                        self.cursor.y + 1
-                    } else {
-                        40
-                    }
+            }
+            Command::Split(id, placement) => {
+                self.split(id, placement);
+            }
+            Command::ClosePane(id) => {
+                self.close_pane(id);
+            }
+            Command::Up | Command::Down | Command::Left | Command::Right => {
+                cursor_command(&command, &mut self.display_buffer, &mut self.cursor);
+            }
+            Command::Save(path) => {
+                self.save(&self.directory.join(path));
+            }
+            Command::Load(path) => {
+                self.load(&self.directory.join(path));
+            }
+            Command::Autosave(autosave) => self.set_autosave(autosave),
+            Command::Import(path) => {
+                self.import(&self.directory.join(path));
+            }
+            Command::Export(path) => {
+                self.export(&self.directory.join(path));
+            }
+            Command::Screenshot(request) => self.screenshots.push(request),
+            Command::Record(recording) => self.recordings.push(recording),
+            Command::Cast(path) => {
+                let path = path.map(|path| self.directory.join(path));
+                self.set_cast(path.as_deref());
+            }
+            Command::Palette(name) => {
+                self.switch_palette(&name);
+            }
+            Command::Play(play) => {
+                let play = play.map(|(path, speed)| (self.directory.join(path), speed));
+                self.play(play.as_ref().map(|(path, speed)| (path.as_path(), *speed)));
+            }
+            Command::InPane(id, commands) => self.run_in_pane(id, &commands),
+        }
+    }
+
+    // Panes only take the cursor commands, everything else belongs to the wall.
+    fn run_in_pane(&mut self, id: u32, commands: &[Command]) {
+        let Some(pane) = self.panes.get_mut(id) else {
+            self.report(format!("No pane {id}"));
+            return;
+        };
+        let refused = commands
+            .iter()
+            .filter(|command| !cursor_command(command, pane.terminal.grid_mut(), &mut pane.cursor))
+            .count();
+        if refused > 0 {
+            self.report("Panes only take w, u, d, l, r and cursor char|fg|bg");
+        }
+    }
+
+    // Called at a fixed rate, `dt` is the length of one step.
+    pub fn update(&mut self, dt: Duration) {
+        self.elapsed += dt;
+        let cursor_position = (self.cursor.x, self.cursor.y);
+
+        for command in std::mem::take(&mut self.commands) {
+            self.run_command(command);
+        }
+
+        if let Some(player) = &mut self.player {
+            if player.advance() {
+                self.display_buffer = player.grid().clone();
+                self.display_buffer.recolor(&self.palette);
+            }
+            if player.finished() {
+                let path = player.path.display().to_string();
+                self.player = None;
+                self.report(format!("Finished playing {path}"));
+            }
+        }
+        let view = self.layout().view();
+        if (self.cursor.x, self.cursor.y) != cursor_position {
+            self.cursor_moved = self.elapsed;
+            self.camera
+                .follow(UVec2::new(self.cursor.x, self.cursor.y), view);
+        }
+        self.camera.clamp(self.display_buffer.size(), view);
+        self.layers.fit(self.display_buffer.size());
+        self.panes.fit(self.window_layout().view());
+        if let Some(layer) = self.layers.get_mut(layer::UI) {
+            self.ui.render(layer, &self.palette);
+        }
+        self.show_cursor();
+
+        if let Some(recorder) = &mut self.cast {
+            if let Err(err) = recorder.capture(&self.display_buffer) {
+                self.cast = None;
+                self.report(format!("Cast recording failed: {err:#}"));
+            }
+        }
+
+        if let Some(autosave) = &self.autosave {
+            if autosave.last.elapsed() >= autosave.interval {
+                let path = autosave.path.clone();
+                self.save(&path);
+                if let Some(autosave) = &mut self.autosave {
+                    autosave.last = Instant::now();
                 }
-                Command::Left => {
-                    self.cursor.x = if self.cursor.x > 0 {
-                        self.cursor.x - 1
-                    } else {
-                        0
-                    }
+            }
+        }
+
+        if let Some((_, shown_at)) = &self.status {
+            if self.elapsed - *shown_at > STATUS_TIME {
+                self.status = None;
+            }
+        }
+    }
+
+    pub const fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: Config) {
+        let palette = config.palette();
+        if palette != self.palette {
+            self.set_palette(palette);
+        }
+        if config.zoom != self.config.zoom {
+            self.zoom = config.zoom;
+        }
+        if config.cursor_style != self.config.cursor_style {
+            self.cursor_style = config.cursor_style;
+        }
+        if config.cursor_blink_ms != self.config.cursor_blink_ms {
+            self.cursor_blink = Duration::from_millis(config.cursor_blink_ms);
+        }
+        self.config = config;
+    }
+
+    pub const fn editing(&self) -> bool {
+        self.editor.enabled
+    }
+
+    pub const fn quitting(&self) -> bool {
+        self.quitting
+    }
+
+    pub const fn shows_stats(&self) -> bool {
+        self.show_stats
+    }
+
+    pub const fn pending_commands(&self) -> usize {
+        self.commands.len()
+    }
+
+    fn change_layer(&mut self, name: &str, action: LayerAction) -> bool {
+        let Some(visible) = self.layers.is_visible(name) else {
+            let names = self.layers.names().join(", ");
+            self.report(format!("Unknown layer {name}, layers are {names}"));
+            return false;
+        };
+        match action {
+            LayerAction::Show => self.layers.set_visible(name, true),
+            LayerAction::Hide => self.layers.set_visible(name, false),
+            LayerAction::Toggle => self.layers.set_visible(name, !visible),
+            LayerAction::Clear => match self.layers.get_mut(name) {
+                Some(layer) => {
+                    layer.clear();
+                    true
                 }
-                Command::Right => {
-                    self.cursor.x = if self.cursor.x < 80 {
-                        self.cursor.x + 1
-                    } else {
-                        80
-                    }
+                None => {
+                    self.report("The content layer is cleared with load or import");
+                    false
+                }
+            },
+            LayerAction::Set(position, tile) => {
+                if self.display_buffer.get(position).is_none() {
+                    self.report(format!("{},{} is outside the wall", position.x, position.y));
+                    return false;
+                }
+                match self.layers.get_mut(name) {
+                    Some(layer) => layer.set(position, Some(tile)),
+                    None => self.display_buffer.set(position, tile),
                 }
-                other => (),
+                true
             }
         }
     }
 
-    fn display_cursor(&mut self) {
-        let Cursor {
-            character,
-            foreground,
-            background,
-            x,
-            y,
-        } = self.cursor;
+    // Returns the id of the new pane.
+    pub fn split(&mut self, id: u32, placement: Placement) -> Option<u32> {
+        let Some(new) = self.panes.split(id, placement) else {
+            self.report(format!("No pane {id}"));
+            return None;
+        };
+        self.panes.fit(self.window_layout().view());
+        self.panes.recolor(&self.palette);
+        self.report(format!("Opened pane {new}"));
+        Some(new)
+    }
 
-        self.display_string(
-            &character.to_string(),
-            UVec2::new(x.into(), y.into()),
-            &foreground,
-            &background,
-        );
+    pub fn close_pane(&mut self, id: u32) -> bool {
+        if id == pane::MAIN {
+            self.report("The main pane can't be closed");
+            return false;
+        }
+        if !self.panes.close(id) {
+            self.report(format!("No pane {id}"));
+            return false;
+        }
+        self.panes.fit(self.window_layout().view());
+        self.report(format!("Closed pane {id}"));
+        true
+    }
+
+    fn change_cursor(&mut self, change: CursorChange) {
+        match change {
+            CursorChange::Style(style) => self.cursor_style = style,
+            CursorChange::Blink(blink) => self.cursor_blink = blink,
+            CursorChange::Character(character) => self.cursor.character = character,
+            CursorChange::Foreground(color, index) => {
+                self.cursor.foreground = color;
+                self.cursor.fg_index = index;
+            }
+            CursorChange::Background(color, index) => {
+                self.cursor.background = color;
+                self.cursor.bg_index = index;
+            }
+        }
+        self.cursor_moved = self.elapsed;
+    }
+
+    fn cursor_shown(&self) -> bool {
+        let blink = self.cursor_blink.as_millis();
+        blink == 0 || (self.elapsed.saturating_sub(self.cursor_moved).as_millis() / blink) % 2 == 0
+    }
+
+    // The cursor layer only ever holds the block cursor, reversed so it stands out from the
+    // wall. Underline and bar cursors are drawn over the tile instead.
+    fn show_cursor(&mut self) {
+        let (tile, x, y) = (self.cursor.tile(), self.cursor.x, self.cursor.y);
+        let block = self.cursor_style == CursorStyle::Block && self.cursor_shown();
+        if let Some(layer) = self.layers.get_mut(layer::CURSOR) {
+            layer.clear();
+            if !block {
+                return;
+            }
+            let tile = tile.with_attributes(Attributes::REVERSE);
+            layer.set(UVec2::new(x, y), Some(tile));
+        }
+    }
+
+    pub const fn grid(&self) -> &Grid {
+        &self.display_buffer
+    }
+
+    pub const fn font(&self) -> &Font {
+        &self.font
+    }
+
+    pub const fn cell_size(&self) -> UVec2 {
+        UVec2::new(self.config.grid_width, self.config.grid_height)
+    }
+
+    pub fn take_ui_events(&mut self) -> Vec<widget::Event> {
+        self.ui.take_events()
+    }
+
+    pub fn take_mouse_events(&mut self) -> Vec<CellEvent> {
+        self.pointer.take_events()
+    }
+
+    // Screenshots need the window's framebuffer, so the app takes them after drawing.
+    pub fn take_screenshots(&mut self) -> Vec<screenshot::Request> {
+        std::mem::take(&mut self.screenshots)
+    }
+
+    pub fn take_recordings(&mut self) -> Vec<RecordRequest> {
+        std::mem::take(&mut self.recordings)
+    }
+
+    pub fn document(&self) -> Document {
+        Document::new(self.display_buffer.clone(), self.cursor.clone())
+    }
+
+    pub fn save(&mut self, path: &Path) -> bool {
+        match save::save(path, &self.document()) {
+            Ok(()) => {
+                self.report(format!("Saved {}", path.display()));
+                true
+            }
+            Err(err) => {
+                self.report(format!("Couldn't save {}: {err:#}", path.display()));
+                false
+            }
+        }
+    }
+
+    pub fn load(&mut self, path: &Path) -> bool {
+        match save::load(path) {
+            Ok(document) => {
+                self.display_buffer = document.grid;
+                self.display_buffer.recolor(&self.palette);
+                self.cursor = document.cursor;
+                self.cursor.recolor(&self.palette);
+                self.report(format!("Loaded {}", path.display()));
+                true
+            }
+            Err(err) => {
+                self.report(format!("Couldn't load {}: {err:#}", path.display()));
+                false
+            }
+        }
+    }
+
+    pub fn import(&mut self, path: &Path) -> bool {
+        match read_import(path) {
+            Ok(imported) => {
+                self.display_buffer = imported.grid;
+                self.display_buffer.recolor(&self.palette);
+                self.sauce = imported.sauce;
+                if let Some(font) = imported.font {
+                    self.font = font;
+                    self.font_changed = true;
+                }
+                self.cursor.x = 0;
+                self.cursor.y = 0;
+                self.report(format!("Imported {}", path.display()));
+                true
+            }
+            Err(err) => {
+                self.report(format!("Couldn't import {}: {err:#}", path.display()));
+                false
+            }
+        }
+    }
+
+    pub fn export(&mut self, path: &Path) -> bool {
+        match self
+            .export_bytes(path)
+            .and_then(|bytes| Ok(fs::write(path, bytes)?))
+        {
+            Ok(()) => {
+                self.report(format!("Exported {}", path.display()));
+                true
+            }
+            Err(err) => {
+                self.report(format!("Couldn't export {}: {err:#}", path.display()));
+                false
+            }
+        }
+    }
+
+    pub fn text(&self, format: TextFormat) -> String {
+        export::render(&self.display_buffer, format, &self.config.title)
+    }
+
+    fn export_bytes(&self, path: &Path) -> anyhow::Result<Vec<u8>> {
+        if xp::is_xp(path) {
+            return xp::export(&self.display_buffer);
+        }
+        if let Some(format) = TextFormat::from_path(path) {
+            return Ok(self.text(format).into_bytes());
+        }
+        let Some(kind) = ansi::Kind::from_path(path) else {
+            anyhow::bail!("unknown format");
+        };
+        let font = (self.font != Font::vga8()).then_some(&self.font);
+        Ok(ansi::export(
+            &self.display_buffer,
+            kind,
+            self.sauce.as_ref(),
+            font,
+        ))
+    }
+
+    pub fn set_directory(&mut self, directory: &Path) {
+        self.directory = directory.to_path_buf();
+    }
+
+    pub fn set_autosave(&mut self, autosave: Option<(String, u64)>) {
+        self.autosave = autosave.map(|(path, seconds)| Autosave {
+            path: self.directory.join(path),
+            interval: Duration::from_secs(seconds.max(1)),
+            last: Instant::now(),
+        });
+        match &self.autosave {
+            Some(autosave) => self.report(format!(
+                "Autosaving to {} every {}s",
+                autosave.path.display(),
+                autosave.interval.as_secs()
+            )),
+            None => self.report("Autosave off"),
+        }
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.display_buffer.recolor(&palette);
+        self.layers.recolor(&palette);
+        self.cursor.recolor(&palette);
+        self.panes.recolor(&palette);
+        self.ui.redraw();
+        self.report(format!("Palette {}", palette.name));
+        self.palette = palette;
+    }
+
+    // Built-in palettes and the `palette.<name>` entries of the config.
+    pub fn switch_palette(&mut self, name: &str) -> bool {
+        let found = self
+            .config
+            .palettes
+            .iter()
+            .rev()
+            .find(|palette| palette.name == name)
+            .cloned()
+            .or_else(|| Palette::builtin(name));
+        match found {
+            Some(palette) => {
+                self.set_palette(palette);
+                true
+            }
+            None => {
+                self.report(format!("Unknown palette {name}"));
+                false
+            }
+        }
+    }
+
+    pub fn background(&self) -> Color {
+        Color::from_hex_rgb(self.config.background)
+    }
+
+    pub fn set_cast(&mut self, path: Option<&Path>) -> bool {
+        if let Some(recorder) = self.cast.take() {
+            self.report(format!("Saved {}", recorder.path.display()));
+        }
+        let Some(path) = path else {
+            return true;
+        };
+        match cast::Recorder::start(path, &self.display_buffer) {
+            Ok(recorder) => {
+                self.cast = Some(recorder);
+                self.report(format!("Recording text to {}", path.display()));
+                true
+            }
+            Err(err) => {
+                self.report(format!("Couldn't record {}: {err:#}", path.display()));
+                false
+            }
+        }
+    }
+
+    pub fn play(&mut self, play: Option<(&Path, f64)>) -> bool {
+        let Some((path, speed)) = play else {
+            self.player = None;
+            self.report("Playback stopped");
+            return true;
+        };
+        match cast::Player::open(path, speed) {
+            Ok(player) => {
+                self.display_buffer = player.grid().clone();
+                self.display_buffer.recolor(&self.palette);
+                self.player = Some(player);
+                self.report(format!("Playing {}", path.display()));
+                true
+            }
+            Err(err) => {
+                self.report(format!("Couldn't play {}: {err:#}", path.display()));
+                false
+            }
+        }
+    }
+
+    pub fn report(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        eprintln!("{message}");
+        self.status = Some((message, self.elapsed));
+    }
+
+    fn display_cursor(&mut self) {
+        let position = UVec2::new(self.cursor.x, self.cursor.y);
+        if self.display_buffer.get(position).is_some() {
+            self.display_buffer.set(position, self.cursor.tile());
+        }
     }
 
     fn draw_char(
@@ -138,125 +826,823 @@ impl Game {
         position: Vec2,
         color: &Color,
         bg_color: &Color,
-        graphics: &mut Graphics2D,
+        canvas: &mut impl Canvas,
     ) {
-        let vga8 = self.spritesheets.get(0).unwrap();
-        let width = self.config.grid_width;
-        let height = self.config.grid_height;
-        let rect = Rect::new(position, position + Vec2::new(width as f32, height as f32));
-        graphics.draw_rectangle(rect.clone(), *bg_color);
-        vga8.draw_sprite_with_color(&rect, 0, (*ch) as u32, *color, graphics);
+        let max = position + self.screen_cell_size().as_vec2();
+        canvas.fill(position, max, *bg_color);
+        canvas.glyph(*ch, position, max, *color);
+    }
+
+    fn draw_cursor(&self, canvas: &mut impl Canvas) {
+        let cursor_layer = self.layers.is_visible(layer::CURSOR) == Some(true);
+        if self.cursor_style == CursorStyle::Block || !cursor_layer || !self.cursor_shown() {
+            return;
+        }
+        let layout = self.layout();
+        let cursor = UVec2::new(self.cursor.x, self.cursor.y);
+        let Some(cell) = layout.to_screen(cursor) else {
+            return;
+        };
+        let size = layout.cell_size().as_vec2();
+        let position = layout.position(cell);
+        let thickness = 2.0 * self.zoom_level() as f32;
+        let (min, max) = match self.cursor_style {
+            CursorStyle::Underline => (
+                position + Vec2::new(0.0, size.y - thickness),
+                position + size,
+            ),
+            _ => (position, position + Vec2::new(thickness, size.y)),
+        };
+        canvas.fill(min, max, self.cursor.foreground);
+    }
+
+    // Thin bars along the right and bottom edges, only for directions the wall can scroll in.
+    fn draw_scrollbars(&self, layout: Layout, canvas: &mut impl Canvas) {
+        const THICKNESS: f32 = 4.0;
+        let grid = self.display_buffer.size().as_vec2();
+        let view = layout.view().as_vec2();
+        let offset = self.camera.offset.as_vec2();
+        let origin = layout.position(UVec2::ZERO);
+        let length = view * layout.cell_size().as_vec2();
+        let end = origin + length;
+        let (track, thumb) = (self.palette.color(8), self.palette.color(7));
+        if grid.y > view.y {
+            let x = end.x - THICKNESS;
+            let from = origin.y + offset.y / grid.y * length.y;
+            let to = origin.y + (offset.y + view.y) / grid.y * length.y;
+            canvas.fill(Vec2::new(x, origin.y), end, track);
+            canvas.fill(Vec2::new(x, from), Vec2::new(end.x, to), thumb);
+        }
+        if grid.x > view.x {
+            let y = end.y - THICKNESS;
+            let from = origin.x + offset.x / grid.x * length.x;
+            let to = origin.x + (offset.x + view.x) / grid.x * length.x;
+            canvas.fill(Vec2::new(origin.x, y), end, track);
+            canvas.fill(Vec2::new(from, y), Vec2::new(to, end.y), thumb);
+        }
+    }
+
+    fn draw_tile(&self, tile: &Tile, position: Vec2, canvas: &mut impl Canvas) {
+        let blink_on = (self.elapsed.as_millis() / BLINK_TIME.as_millis()) % 2 == 0;
+        let size = self.screen_cell_size().as_vec2();
+        let zoom = self.zoom_level() as f32;
+        raster::draw_tile(canvas, tile, position, size, zoom, blink_on);
     }
 
     pub fn clear_buffer(&mut self) {
-        self.display_buffer = [[Tile::new(' '); 80]; 40];
+        self.display_buffer.clear(&self.palette);
+    }
+
+    // Like `display_string`, with colours that follow palette swaps.
+    pub fn display_text(&mut self, str: &str, position: UVec2, fg: u8, bg: u8) {
+        let UVec2 { x, y } = position;
+        for (i, ch) in str.chars().enumerate() {
+            let tile = Tile::new(ch)
+                .with_fg_index(fg, &self.palette)
+                .with_bg_index(bg, &self.palette);
+            self.display_buffer.set(UVec2::new(x + i as u32, y), tile);
+        }
     }
 
     pub fn display_string(&mut self, str: &str, position: UVec2, color: &Color, bg_color: &Color) {
         let UVec2 { x, y } = position;
         for (i, ch) in str.chars().enumerate() {
             let tile = Tile::new(ch).with_bg(*bg_color).with_fg(*color);
-            if x >= 80 || y >= 40 {
+            let position = UVec2::new(x + i as u32, y);
+            if self.display_buffer.get(position).is_none() {
                 log::warn!("Part of the string is offscreen, no wrapping");
                 continue;
             }
-            self.display_buffer[y as usize][x as usize + i] = tile;
+            self.display_buffer.set(position, tile);
+        }
+    }
+
+    // A palette index like `12` or a hex colour like `#ff8800`, with the index if there is one.
+    fn parse_color(&self, text: &str) -> Option<(Color, Option<u8>)> {
+        match text.parse::<u8>() {
+            Ok(idx) => Some((self.palette.color(idx), Some(idx))),
+            Err(_) => palette::parse_hex(text).map(|hex| (Color::from_hex_rgb(hex), None)),
+        }
+    }
+
+    // `<x,y> <char> [fg bg]` of `layer set`, colours default to 15 on 0.
+    fn parse_layer_tile<'a>(
+        &self,
+        args: &mut impl Iterator<Item = &'a str>,
+    ) -> Option<(UVec2, Tile)> {
+        let position = widget::parse_cell(args.next()?)?;
+        let mut chars = args.next()?.chars();
+        let ch = match (chars.next(), chars.next()) {
+            (Some(ch), None) => from_unicode(ch),
+            _ => return None,
+        };
+        let mut tile = Tile::new(ch);
+        tile.recolor(&self.palette);
+        match (args.next(), args.next()) {
+            (None, _) => (),
+            (Some(fg), Some(bg)) => {
+                let ((fg, fg_index), (bg, bg_index)) =
+                    (self.parse_color(fg)?, self.parse_color(bg)?);
+                tile = Tile {
+                    fg,
+                    bg,
+                    fg_index,
+                    bg_index,
+                    ..tile
+                };
+            }
+            (Some(_), None) => return None,
         }
+        Some((position, tile))
     }
 
     pub fn apply_command(&mut self, command: &str) {
-        let commands = command.split('-');
-        for command in commands {
-            let words = command.split(' ');
-            for word in words {
-                self.apply_word(word);
+        // `pane <id> print <text>` hands the text to the pane's terminal untouched, other
+        // commands after `pane <id>` run against the pane.
+        if let Some((id, text)) = pane_target(command) {
+            let printed = text
+                .strip_prefix("print ")
+                .or((text == "print").then_some(""));
+            match (id, printed) {
+                (pane::MAIN, Some(_)) => self.report("The wall has no terminal to print to"),
+                (pane::MAIN, None) => self.apply_command(text),
+                (id, Some(line)) => self.commands.push(Command::PaneText(id, line.to_string())),
+                (id, None) => {
+                    let wall = std::mem::take(&mut self.commands);
+                    self.apply_command(text);
+                    let commands = std::mem::replace(&mut self.commands, wall);
+                    self.commands.push(Command::InPane(id, commands));
+                }
+            }
+            return;
+        }
+        let mut words = command.split_whitespace();
+        while let Some(word) = words.next() {
+            if ARGUMENT_WORDS.contains(&word) {
+                self.apply_word(word, &mut words);
+                continue;
+            }
+            for word in word.split('-') {
+                self.apply_word(word, &mut words);
             }
         }
     }
 
-    pub fn apply_word(&mut self, word: &str) {
+    pub fn apply_word<'a>(&mut self, word: &str, args: &mut impl Iterator<Item = &'a str>) {
         match word {
             "w" => self.commands.push(Command::Write),
             "u" => self.commands.push(Command::Up),
             "d" => self.commands.push(Command::Down),
             "l" => self.commands.push(Command::Left),
             "r" => self.commands.push(Command::Right),
-            other => self.display_string(word, UVec2::new(0, 40), &Color::BLUE, &Color::WHITE),
+            "edit" => self.commands.push(Command::ToggleEditor),
+            "tool" => match args.next().map(|name| (name, name.parse::<Tool>())) {
+                Some(("next", _)) => self.commands.push(Command::Tool(None)),
+                Some((_, Ok(tool))) => self.commands.push(Command::Tool(Some(tool))),
+                Some((_, Err(err))) => self.report(err),
+                None => self.report("Usage: tool <pencil|line|rect|fill|text|pick|next>"),
+            },
+            "quit" => self.commands.push(Command::Quit),
+            "stats" => self.commands.push(Command::ToggleStats),
+            "save" | "load" | "import" | "export" => {
+                let Some(path) = args.next() else {
+                    self.report(format!("Usage: {word} <path>"));
+                    return;
+                };
+                let path = path.to_string();
+                self.commands.push(match word {
+                    "save" => Command::Save(path),
+                    "load" => Command::Load(path),
+                    "import" => Command::Import(path),
+                    _ => Command::Export(path),
+                });
+            }
+            "autosave" => match args.next() {
+                Some("off") => self.commands.push(Command::Autosave(None)),
+                Some(path) => match args.next().map_or(Ok(60), str::parse::<u64>) {
+                    Ok(seconds) => self
+                        .commands
+                        .push(Command::Autosave(Some((path.to_string(), seconds)))),
+                    Err(_) => self.report("Usage: autosave <path> [seconds] | autosave off"),
+                },
+                None => self.report("Usage: autosave <path> [seconds] | autosave off"),
+            },
+            "record" => match args.next() {
+                None => self.commands.push(Command::Record(RecordRequest::Toggle)),
+                Some("off") => self.commands.push(Command::Record(RecordRequest::Stop)),
+                Some(path) => match args.next().map_or(Ok(1), str::parse::<u64>) {
+                    Ok(every) => {
+                        let request = RecordRequest::Start(path.to_string(), every);
+                        self.commands.push(Command::Record(request));
+                    }
+                    Err(_) => self.report(
+                        "Usage: record | record <path.gif|path.png|folder> [every nth frame] | record off",
+                    ),
+                },
+            },
+            "cast" => match args.next() {
+                Some("off") => self.commands.push(Command::Cast(None)),
+                Some(path) => self.commands.push(Command::Cast(Some(path.to_string()))),
+                None => self.report("Usage: cast <path.cast> | cast off"),
+            },
+            "play" => match args.next() {
+                Some("off") => self.commands.push(Command::Play(None)),
+                Some(path) => match args.next().map_or(Ok(1.0), str::parse::<f64>) {
+                    Ok(speed) => self
+                        .commands
+                        .push(Command::Play(Some((path.to_string(), speed)))),
+                    Err(_) => self.report("Usage: play <path.cast> [speed] | play off"),
+                },
+                None => self.report("Usage: play <path.cast> [speed] | play off"),
+            },
+            "screenshot" => {
+                let usage = "Usage: screenshot [<path.png|path.jpeg> [x,y,width,height]]";
+                // Without a path the app names a JPEG in the screenshot folder.
+                let Some(path) = args.next() else {
+                    self.commands.push(Command::Screenshot(screenshot::Request {
+                        path: None,
+                        format: screenshot::Format::Jpeg,
+                        region: None,
+                    }));
+                    return;
+                };
+                let Some(format) = screenshot::Format::from_path(Path::new(path)) else {
+                    self.report(usage);
+                    return;
+                };
+                let region = match args.next().map(Region::parse) {
+                    None => None,
+                    Some(Some(region)) => Some(region),
+                    Some(None) => {
+                        self.report(usage);
+                        return;
+                    }
+                };
+                self.commands.push(Command::Screenshot(screenshot::Request {
+                    path: Some(path.to_string()),
+                    format,
+                    region,
+                }));
+            }
+            "palette" => match args.next() {
+                Some(name) => self.commands.push(Command::Palette(name.to_string())),
+                None => self.report("Usage: palette <name>"),
+            },
+            "cursor" => {
+                let usage = "Usage: cursor block|underline|bar | cursor blink <ms> | cursor char <c> | cursor fg|bg <index|hex>";
+                // Only the arms taking a value read it, so `cursor bar w` still writes.
+                let change = match args.next() {
+                    Some(style @ ("block" | "underline" | "bar")) => {
+                        style.parse().ok().map(CursorChange::Style)
+                    }
+                    Some("blink") => args
+                        .next()
+                        .and_then(|ms| ms.parse().ok())
+                        .map(|ms| CursorChange::Blink(Duration::from_millis(ms))),
+                    Some("char") => args.next().and_then(|text| {
+                        let mut chars = text.chars();
+                        match (chars.next(), chars.next()) {
+                            (Some(ch), None) => Some(CursorChange::Character(ch)),
+                            _ => None,
+                        }
+                    }),
+                    Some("fg") => args
+                        .next()
+                        .and_then(|color| self.parse_color(color))
+                        .map(|(color, index)| CursorChange::Foreground(color, index)),
+                    Some("bg") => args
+                        .next()
+                        .and_then(|color| self.parse_color(color))
+                        .map(|(color, index)| CursorChange::Background(color, index)),
+                    _ => None,
+                };
+                match change {
+                    Some(change) => self.commands.push(Command::Cursor(change)),
+                    None => self.report(usage),
+                }
+            }
+            "zoom" => match args.next() {
+                Some("in") => self.commands.push(Command::ZoomBy(1)),
+                Some("out") => self.commands.push(Command::ZoomBy(-1)),
+                Some(zoom) => match zoom.parse() {
+                    Ok(zoom) => self.commands.push(Command::Zoom(zoom)),
+                    Err(err) => self.report(format!("Usage: zoom in|out|auto|1-8, {err}")),
+                },
+                None => self.report("Usage: zoom in|out|auto|1-8"),
+            },
+            "mouse" => match args.next() {
+                Some("on") => self.commands.push(Command::MouseEcho(true)),
+                Some("off") => self.commands.push(Command::MouseEcho(false)),
+                _ => self.report("Usage: mouse on|off"),
+            },
+            "ui" => match UiChange::parse(args, self.display_buffer.size()) {
+                Some(change) => self.commands.push(Command::Ui(change)),
+                None => self.report(widget::USAGE),
+            },
+            "pane" => {
+                let usage = "Usage: pane <id> <commands> | pane <id> print <text> | pane split <id> right|below | pane close|clear <id> | pane list";
+                let command = match args.next() {
+                    Some("list") => {
+                        let ids = self.panes.ids();
+                        let ids: Vec<String> = ids.iter().map(u32::to_string).collect();
+                        self.report(format!("Panes {}", ids.join(", ")));
+                        return;
+                    }
+                    Some("split") => parse_next(args).and_then(|id| match args.next() {
+                        Some("right") => Some(Command::Split(id, Placement::Right)),
+                        Some("below") => Some(Command::Split(id, Placement::Below)),
+                        _ => None,
+                    }),
+                    Some("close") => parse_next(args).map(Command::ClosePane),
+                    Some("clear") => parse_next(args).map(Command::PaneClear),
+                    _ => None,
+                };
+                match command {
+                    Some(command) => self.commands.push(command),
+                    None => self.report(usage),
+                }
+            }
+            "layer" => {
+                let usage = "Usage: layer show|hide|toggle|clear <name> | layer set <name> <x,y> <char> [fg bg]";
+                let action = match args.next() {
+                    Some("show") => Some(LayerAction::Show),
+                    Some("hide") => Some(LayerAction::Hide),
+                    Some("toggle") => Some(LayerAction::Toggle),
+                    Some("clear") => Some(LayerAction::Clear),
+                    Some("set") => {
+                        let Some(name) = args.next() else {
+                            self.report(usage);
+                            return;
+                        };
+                        match self.parse_layer_tile(args) {
+                            Some((position, tile)) => self.commands.push(Command::Layer(
+                                name.to_string(),
+                                LayerAction::Set(position, tile),
+                            )),
+                            None => self.report(usage),
+                        }
+                        return;
+                    }
+                    _ => None,
+                };
+                match (action, args.next()) {
+                    (Some(action), Some(name)) => {
+                        self.commands.push(Command::Layer(name.to_string(), action));
+                    }
+                    _ => self.report(usage),
+                }
+            }
+            other => self.display_text(word, UVec2::new(0, 40), 1, 15),
+        }
+    }
+
+    // Returns how many tiles were drawn.
+    pub fn draw(&self, graphics: &mut Graphics2D) -> usize {
+        self.draw_to(&mut self.screen(graphics))
+    }
+
+    // The window's contents as an image, for running without one.
+    pub fn frame(&self) -> RgbaImage {
+        let mut canvas = raster::Image::new(self.viewport_size, self.background(), &self.font);
+        self.draw_to(&mut canvas);
+        canvas.image
+    }
+
+    fn screen<'a>(&'a self, graphics: &'a mut Graphics2D) -> Screen<'a> {
+        Screen {
+            graphics,
+            font: self.spritesheets.first().unwrap(),
+        }
+    }
+
+    fn draw_to(&self, canvas: &mut impl Canvas) -> usize {
+        let layout = self.layout();
+        let view = layout.view();
+        let mut drawn = 0;
+        for y in 0..view.y {
+            for x in 0..view.x {
+                let cell = layout.to_grid(UVec2::new(x, y));
+                let Some(tile) = self.layers.composite(&self.display_buffer, cell) else {
+                    continue;
+                };
+                self.draw_tile(&tile, layout.position(UVec2::new(x, y)), canvas);
+                drawn += 1;
+            }
+        }
+        self.draw_cursor(canvas);
+        self.draw_scrollbars(layout, canvas);
+        for (cell, tile) in self.editor.overlay(layout, &self.palette) {
+            self.draw_tile(&tile, layout.position(cell), canvas);
+            drawn += 1;
+        }
+        let window = self.window_layout();
+        drawn += self.draw_panes(window, canvas);
+        if let Some((status, _)) = &self.status {
+            let y = window.view().y.saturating_sub(1);
+            for (x, ch) in status.chars().enumerate() {
+                let pos = window.position(UVec2::new(x as u32, y));
+                let (fg, bg) = (self.palette.color(0), self.palette.color(15));
+                self.draw_char(&ch, pos, &fg, &bg, canvas);
+                drawn += 1;
+            }
         }
+        drawn
     }
 
-    pub fn draw(&self, graphics: &mut Graphics2D) {
-        let width = self.config.grid_width;
-        let height = self.config.grid_height;
-        for (y, row) in self.display_buffer.iter().enumerate() {
-            for (x, tile) in row.iter().enumerate() {
-                let pos = Vec2::new((x * width as usize) as f32, (y * height as usize) as f32);
-                let Tile { ch, fg, bg } = tile;
-                self.draw_char(ch, pos, fg, bg, graphics);
+    // Every pane but the main one, and the borders between them.
+    fn draw_panes(&self, window: Layout, canvas: &mut impl Canvas) -> usize {
+        let (areas, borders) = self.panes.arrange(window.view());
+        let mut drawn = 0;
+        for pane in self.panes.iter() {
+            let Some((_, area)) = areas.iter().find(|(id, _)| *id == pane.id) else {
+                continue;
+            };
+            let grid = pane.terminal.grid();
+            for y in 0..area.size.y.min(grid.height()) {
+                for x in 0..area.size.x.min(grid.width()) {
+                    let Some(tile) = grid.get(UVec2::new(x, y)) else {
+                        continue;
+                    };
+                    let cell = area.min + UVec2::new(x, y);
+                    self.draw_tile(tile, window.position(cell), canvas);
+                    drawn += 1;
+                }
+            }
+        }
+        let (fg, bg) = (self.palette.color(7), self.palette.color(0));
+        for &cell in &borders {
+            let glyph = pane::border_glyph(cell, &borders);
+            self.draw_char(&glyph, window.position(cell), &fg, &bg, canvas);
+            drawn += 1;
+        }
+        drawn
+    }
+
+    // Text box in the top right corner, on top of everything else.
+    pub fn draw_overlay(&self, lines: &[String], graphics: &mut Graphics2D) {
+        let canvas = &mut self.screen(graphics);
+        let layout = self.window_layout();
+        let columns = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0) as u32;
+        let left = layout.view().x.saturating_sub(columns);
+        let (fg, bg) = (self.palette.color(14), self.palette.color(1));
+        for (y, line) in lines.iter().enumerate() {
+            let line = format!("{line:<width$}", width = columns as usize);
+            for (x, ch) in line.chars().enumerate() {
+                let pos = layout.position(UVec2::new(left + x as u32, y as u32));
+                self.draw_char(&ch, pos, &fg, &bg, canvas);
             }
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+// The window as a canvas, glyphs come from the uploaded font.
+struct Screen<'a> {
+    graphics: &'a mut Graphics2D,
+    font: &'a Spritesheet,
+}
+
+impl Canvas for Screen<'_> {
+    fn fill(&mut self, min: Vec2, max: Vec2, color: Color) {
+        self.graphics.draw_rectangle(Rect::new(min, max), color);
+    }
+
+    fn glyph(&mut self, ch: char, min: Vec2, max: Vec2, color: Color) {
+        let rect = Rect::new(min, max);
+        self.font
+            .draw_sprite_with_color(&rect, 0, u32::from(ch), color, self.graphics);
+    }
+}
+
+fn read_import(path: &Path) -> anyhow::Result<ansi::Imported> {
+    let bytes = fs::read(path)?;
+    if xp::is_xp(path) {
+        return Ok(ansi::Imported {
+            grid: xp::import(&bytes)?,
+            sauce: None,
+            font: None,
+        });
+    }
+    let Some(kind) = ansi::Kind::from_path(path) else {
+        anyhow::bail!("unknown format");
+    };
+    ansi::import(&bytes, kind)
+}
+
+// Runs the commands that only need a grid and a cursor, for the wall and for panes.
+// Returns false for any other command.
+fn cursor_command(command: &Command, grid: &mut Grid, cursor: &mut Cursor) -> bool {
+    let last = grid.size().max(UVec2::ONE) - UVec2::ONE;
+    match command {
+        Command::Write => grid.set(UVec2::new(cursor.x, cursor.y), cursor.tile()),
+        Command::Up => cursor.y = cursor.y.saturating_sub(1),
+        Command::Down => cursor.y = (cursor.y + 1).min(last.y),
+        Command::Left => cursor.x = cursor.x.saturating_sub(1),
+        Command::Right => cursor.x = (cursor.x + 1).min(last.x),
+        Command::Cursor(CursorChange::Character(character)) => cursor.character = *character,
+        Command::Cursor(CursorChange::Foreground(color, index)) => {
+            cursor.foreground = *color;
+            cursor.fg_index = *index;
+        }
+        Command::Cursor(CursorChange::Background(color, index)) => {
+            cursor.background = *color;
+            cursor.bg_index = *index;
+        }
+        _ => return false,
+    }
+    true
+}
+
+// The next argument, if there is one and it parses.
+fn parse_next<'a, T: FromStr>(args: &mut impl Iterator<Item = &'a str>) -> Option<T> {
+    args.next().and_then(|arg| arg.parse().ok())
+}
+
+// `pane <id> <commands>`, as opposed to `pane split ...` and the other pane commands.
+fn pane_target(command: &str) -> Option<(u32, &str)> {
+    let rest = command.trim_start().strip_prefix("pane ")?.trim_start();
+    let (id, text) = rest.split_once(' ').unwrap_or((rest, ""));
+    Some((id.parse().ok()?, text))
+}
+
+const ARGUMENT_WORDS: [&str; 17] = [
+    "tool",
+    "save",
+    "load",
+    "autosave",
+    "import",
+    "export",
+    "screenshot",
+    "record",
+    "cast",
+    "play",
+    "palette",
+    "layer",
+    "cursor",
+    "zoom",
+    "pane",
+    "ui",
+    "mouse",
+];
+
+#[derive(Clone, Debug)]
 enum Command {
     Write,
     Up,
     Down,
     Left,
     Right,
+    ToggleEditor,
+    // `None` moves to the next tool.
+    Tool(Option<Tool>),
+    Quit,
+    ToggleStats,
+    Layer(String, LayerAction),
+    Cursor(CursorChange),
+    Zoom(Zoom),
+    ZoomBy(i32),
+    Ui(UiChange),
+    MouseEcho(bool),
+    PaneText(u32, String),
+    PaneClear(u32),
+    Split(u32, Placement),
+    ClosePane(u32),
+    // Commands run against a pane's grid and cursor instead of the wall's.
+    InPane(u32, Vec<Self>),
+    Save(String),
+    Load(String),
+    Autosave(Option<(String, u64)>),
+    Import(String),
+    Export(String),
+    Screenshot(screenshot::Request),
+    Record(RecordRequest),
+    Cast(Option<String>),
+    Play(Option<(String, f64)>),
+    Palette(String),
 }
 
+// The app owns the recording, `Toggle` starts one in the screenshot folder or stops it.
 #[derive(Clone, Debug)]
-struct Cursor {
-    character: char,
-    foreground: Color,
-    background: Color,
-    x: u8,
-    y: u8,
+pub enum RecordRequest {
+    Start(String, u64),
+    Stop,
+    Toggle,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum CursorChange {
+    Style(CursorStyle),
+    Blink(Duration),
+    Character(char),
+    Foreground(Color, Option<u8>),
+    Background(Color, Option<u8>),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum LayerAction {
+    Show,
+    Hide,
+    Toggle,
+    Clear,
+    Set(UVec2, Tile),
+}
+
+struct Autosave {
+    path: PathBuf,
+    interval: Duration,
+    last: Instant,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Cursor {
+    pub character: char,
+    #[serde(with = "rgba")]
+    pub foreground: Color,
+    #[serde(with = "rgba")]
+    pub background: Color,
+    // Palette indices like `Tile` has, missing from saves made before they existed.
+    #[serde(default)]
+    pub fg_index: Option<u8>,
+    #[serde(default)]
+    pub bg_index: Option<u8>,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Self::new(Tile::new('a'), 0, 0)
+    }
 }
 
 impl Cursor {
-    pub const fn new(character: char, foreground: Color, background: Color, x: u8, y: u8) -> Self {
+    // Character and colours come from `tile`.
+    pub const fn new(tile: Tile, x: u32, y: u32) -> Self {
         Self {
-            character,
-            foreground,
-            background,
+            character: tile.ch,
+            foreground: tile.fg,
+            background: tile.bg,
+            fg_index: tile.fg_index,
+            bg_index: tile.bg_index,
             x,
             y,
         }
     }
+
+    // What `w` stamps on the wall.
+    pub const fn tile(&self) -> Tile {
+        Tile {
+            fg_index: self.fg_index,
+            bg_index: self.bg_index,
+            ..Tile::new(self.character)
+                .with_fg(self.foreground)
+                .with_bg(self.background)
+        }
+    }
+
+    pub fn recolor(&mut self, palette: &Palette) {
+        if let Some(idx) = self.fg_index {
+            self.foreground = palette.color(idx);
+        }
+        if let Some(idx) = self.bg_index {
+            self.background = palette.color(idx);
+        }
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
-struct Tile {
-    ch: char,
-    fg: Color,
-    bg: Color,
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CursorStyle {
+    #[default]
+    Block,
+    Underline,
+    Bar,
 }
 
-impl Tile {
-    pub const fn new(ch: char) -> Self {
-        Self {
-            ch,
-            fg: Color::WHITE,
-            bg: Color::BLACK,
+impl FromStr for CursorStyle {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "block" => Ok(Self::Block),
+            "underline" => Ok(Self::Underline),
+            "bar" => Ok(Self::Bar),
+            other => Err(format!("`{other}` is not block, underline or bar")),
         }
     }
-    pub const fn with_fg(self, color: Color) -> Self {
-        Self { fg: color, ..self }
-    }
-    pub const fn with_bg(self, color: Color) -> Self {
-        Self { bg: color, ..self }
-    }
 }
 
-impl Default for Tile {
-    fn default() -> Self {
-        Self {
-            ch: ' ',
-            fg: Color::WHITE,
-            bg: Color::BLACK,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::color_to_rgba;
+
+    #[test]
+    fn palette_swap_recolors_blank_wall() {
+        let mut game = Game::new(Config::default());
+        game.display_cursor();
+        let solarized = Palette::builtin("solarized").unwrap();
+        game.set_palette(solarized.clone());
+        let (fg, bg) = (solarized.color(15), solarized.color(0));
+        for tile in game.display_buffer.tiles() {
+            assert_eq!((tile.fg, tile.bg), (fg, bg));
         }
+        assert_eq!((game.cursor.foreground, game.cursor.background), (fg, bg));
+    }
+
+    #[test]
+    fn layer_set_writes_into_a_layer() {
+        let mut game = Game::new(Config::default());
+        game.apply_command("layer set background 2,3 # 12 4");
+        game.apply_command("layer set nowhere 0,0 #");
+        game.apply_command("layer set background 2,3 # 12");
+        game.update(Duration::ZERO);
+        let cell = UVec2::new(2, 3);
+        let tile = game.layers.composite(&game.display_buffer, cell).unwrap();
+        assert_eq!(tile.ch, '#');
+        assert_eq!((tile.fg_index, tile.bg_index), (Some(12), Some(4)));
+    }
+
+    #[test]
+    fn screenshot_regions_follow_the_camera() {
+        let mut game = Game::new(Config::default());
+        let layout = game.layout();
+        let cell_size = layout.cell_size();
+        let region = Region::parse("1,1,2,2").unwrap();
+        let expected = (layout.position(UVec2::ONE).as_uvec2(), cell_size * 2);
+        assert_eq!(game.screen_area(region), Some(expected));
+
+        // Cells scrolled off the left edge are left out.
+        game.camera.offset = UVec2::new(2, 0);
+        let region = Region::parse("0,0,4,1").unwrap();
+        let size = cell_size * UVec2::new(2, 1);
+        let expected = (layout.position(UVec2::ZERO).as_uvec2(), size);
+        assert_eq!(game.screen_area(region), Some(expected));
+        assert_eq!(game.screen_area(Region::parse("0,0,2,1").unwrap()), None);
+    }
+
+    #[test]
+    fn pane_commands_use_the_pane_grid() {
+        let mut game = Game::new(Config::default());
+        let wall = game.display_buffer.clone();
+        let id = game.split(pane::MAIN, Placement::Right).unwrap();
+        game.apply_command(&format!("pane {id} cursor char x"));
+        game.apply_command(&format!("pane {id} r-w"));
+        // Only cursor commands run in a pane.
+        game.apply_command(&format!("pane {id} pane close {id}"));
+        game.apply_command(&format!("pane {id} palette solarized"));
+        game.update(Duration::ZERO);
+        assert_eq!(game.display_buffer, wall);
+        assert_eq!((game.cursor.x, game.cursor.y), (0, 0));
+        assert_eq!(game.palette.name, "vga");
+        assert_eq!(game.panes.ids(), [pane::MAIN, id]);
+        let pane = game.panes.get_mut(id).unwrap();
+        assert_eq!((pane.cursor.x, pane.cursor.y), (1, 0));
+        assert_eq!(pane.terminal.grid().get(UVec2::new(1, 0)).unwrap().ch, 'x');
+    }
+
+    #[test]
+    fn pane_text_uses_the_palette() {
+        let mut game = Game::new(Config::default());
+        let id = game.split(pane::MAIN, Placement::Right).unwrap();
+        let solarized = Palette::builtin("solarized").unwrap();
+        game.set_palette(solarized.clone());
+        game.apply_command(&format!("pane {id} print hi"));
+        game.update(Duration::ZERO);
+        let pane = game.panes.get_mut(id).unwrap();
+        let h = *pane.terminal.grid().get(UVec2::ZERO).unwrap();
+        assert_eq!(h.ch, 'h');
+        assert_eq!((h.fg, h.bg), (solarized.color(7), solarized.color(0)));
+    }
+
+    #[test]
+    fn frames_show_layers_cursor_and_panes() {
+        let mut game = Game::new(Config::default());
+        game.split(pane::MAIN, Placement::Right).unwrap();
+        game.apply_command("layer set background 2,3 # 12 4");
+        game.update(Duration::ZERO);
+        let frame = game.frame();
+        assert_eq!(UVec2::from(frame.dimensions()), game.viewport_size);
+
+        // Top left pixels of the glyphs are unlit, so they show the background.
+        let layout = game.layout();
+        let pixel = |cell: UVec2| {
+            let position = layout.position(layout.to_screen(cell).unwrap()).as_uvec2();
+            frame.get_pixel(position.x, position.y).0
+        };
+        let background = game.palette.color(4);
+        assert_eq!(pixel(UVec2::new(2, 3)), color_to_rgba(background));
+        // The block cursor is reversed.
+        assert_eq!(pixel(UVec2::ZERO), color_to_rgba(game.cursor.foreground));
+
+        let window = game.window_layout();
+        let (_, borders) = game.panes.arrange(window.view());
+        let min = window.position(borders[0]).as_uvec2();
+        let size = window.cell_size();
+        let border = image::imageops::crop_imm(&frame, min.x, min.y, size.x, size.y);
+        let line = image::Rgba(color_to_rgba(game.palette.color(7)));
+        assert!(border.to_image().pixels().any(|pixel| *pixel == line));
     }
 }
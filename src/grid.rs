@@ -0,0 +1,276 @@
+use glam::UVec2;
+use serde::{Deserialize, Serialize};
+use speedy2d::color::Color;
+
+use crate::palette::Palette;
+
+// Sizes read from files are capped, so a bad header can't allocate gigabytes.
+pub const MAX_CELLS: u32 = 1 << 22;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Attributes(pub u8);
+
+impl Attributes {
+    pub const NONE: Self = Self(0);
+    pub const BOLD: Self = Self(1);
+    pub const UNDERLINE: Self = Self(1 << 1);
+    pub const BLINK: Self = Self(1 << 2);
+    pub const REVERSE: Self = Self(1 << 3);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn with(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn without(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tile {
+    pub ch: char,
+    #[serde(with = "rgba")]
+    pub fg: Color,
+    #[serde(with = "rgba")]
+    pub bg: Color,
+    #[serde(default)]
+    pub attributes: Attributes,
+    // Palette entries the colours came from, `None` for plain RGB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fg_index: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bg_index: Option<u8>,
+}
+
+impl Tile {
+    pub const fn new(ch: char) -> Self {
+        Self {
+            ch,
+            fg: Color::WHITE,
+            bg: Color::BLACK,
+            attributes: Attributes::NONE,
+            // Palette colours 15 and 0, so blank cells follow palette swaps too.
+            fg_index: Some(15),
+            bg_index: Some(0),
+        }
+    }
+    pub const fn with_fg(self, color: Color) -> Self {
+        Self {
+            fg: color,
+            fg_index: None,
+            ..self
+        }
+    }
+    pub const fn with_bg(self, color: Color) -> Self {
+        Self {
+            bg: color,
+            bg_index: None,
+            ..self
+        }
+    }
+    pub fn with_fg_index(self, idx: u8, palette: &Palette) -> Self {
+        Self {
+            fg: palette.color(idx),
+            fg_index: Some(idx),
+            ..self
+        }
+    }
+    pub fn with_bg_index(self, idx: u8, palette: &Palette) -> Self {
+        Self {
+            bg: palette.color(idx),
+            bg_index: Some(idx),
+            ..self
+        }
+    }
+    pub const fn with_attributes(self, attributes: Attributes) -> Self {
+        Self { attributes, ..self }
+    }
+
+    // Only indexed colours follow the palette.
+    pub fn recolor(&mut self, palette: &Palette) {
+        if let Some(idx) = self.fg_index {
+            self.fg = palette.color(idx);
+        }
+        if let Some(idx) = self.bg_index {
+            self.bg = palette.color(idx);
+        }
+    }
+
+    // A cell nothing was written to in `palette`'s colours.
+    pub fn blank(palette: &Palette) -> Self {
+        let mut tile = Self::default();
+        tile.recolor(palette);
+        tile
+    }
+
+    // A cell nothing was written to, a space in palette colours 15 and 0.
+    pub fn is_blank(&self) -> bool {
+        self.ch == ' '
+            && self.attributes == Attributes::NONE
+            && self.fg_index == Some(15)
+            && self.bg_index == Some(0)
+    }
+
+    // Colors as they should appear on screen, `blink_on` is false during the hidden blink phase.
+    pub fn shown_colors(&self, blink_on: bool) -> (Color, Color) {
+        let (fg, bg) = if self.attributes.contains(Attributes::REVERSE) {
+            (self.bg, self.fg)
+        } else {
+            (self.fg, self.bg)
+        };
+        if self.attributes.contains(Attributes::BLINK) && !blink_on {
+            (bg, bg)
+        } else {
+            (fg, bg)
+        }
+    }
+}
+
+impl Default for Tile {
+    fn default() -> Self {
+        Self::new(' ')
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawGrid")]
+pub struct Grid {
+    width: u32,
+    height: u32,
+    tiles: Vec<Tile>,
+}
+
+// Deserialized grids go through `Grid::from_tiles`, so the tile count always matches.
+#[derive(Deserialize)]
+struct RawGrid {
+    width: u32,
+    height: u32,
+    tiles: Vec<Tile>,
+}
+
+impl TryFrom<RawGrid> for Grid {
+    type Error = String;
+
+    fn try_from(raw: RawGrid) -> Result<Self, Self::Error> {
+        let RawGrid {
+            width,
+            height,
+            tiles,
+        } = raw;
+        let count = tiles.len();
+        Self::from_tiles(width, height, tiles)
+            .ok_or_else(|| format!("{width}x{height} grid with {count} tiles"))
+    }
+}
+
+impl Grid {
+    // Blank in VGA colours, `recolor` it for another palette. Sizes from files have to be
+    // checked against `MAX_CELLS` first.
+    pub fn new(width: u32, height: u32) -> Self {
+        let count = width
+            .checked_mul(height)
+            .unwrap_or_else(|| panic!("{width}x{height} grid is too big"));
+        Self {
+            width,
+            height,
+            tiles: vec![Tile::default(); count as usize],
+        }
+    }
+
+    pub fn from_tiles(width: u32, height: u32, tiles: Vec<Tile>) -> Option<Self> {
+        let count = width.checked_mul(height)? as usize;
+        (tiles.len() == count).then_some(Self {
+            width,
+            height,
+            tiles,
+        })
+    }
+
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub const fn size(&self) -> UVec2 {
+        UVec2::new(self.width, self.height)
+    }
+
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    pub fn rows(&self) -> std::slice::Chunks<'_, Tile> {
+        self.tiles.chunks(self.width.max(1) as usize)
+    }
+
+    fn index(&self, position: UVec2) -> Option<usize> {
+        (position.x < self.width && position.y < self.height)
+            .then_some((position.y * self.width + position.x) as usize)
+    }
+
+    pub fn get(&self, position: UVec2) -> Option<&Tile> {
+        self.index(position).map(|idx| &self.tiles[idx])
+    }
+
+    pub fn get_mut(&mut self, position: UVec2) -> Option<&mut Tile> {
+        self.index(position).map(|idx| &mut self.tiles[idx])
+    }
+
+    pub fn set(&mut self, position: UVec2, tile: Tile) {
+        if let Some(target) = self.get_mut(position) {
+            *target = tile;
+        }
+    }
+
+    pub fn recolor(&mut self, palette: &Palette) {
+        for tile in &mut self.tiles {
+            tile.recolor(palette);
+        }
+    }
+
+    pub fn clear(&mut self, palette: &Palette) {
+        self.tiles.fill(Tile::blank(palette));
+    }
+
+    // New cells are blank in `palette`'s colours.
+    pub fn resize(&mut self, width: u32, height: u32, palette: &Palette) {
+        let mut resized = Self::new(width, height);
+        resized.clear(palette);
+        for y in 0..height.min(self.height) {
+            for x in 0..width.min(self.width) {
+                let position = UVec2::new(x, y);
+                resized.set(position, self.tiles[self.index(position).unwrap()]);
+            }
+        }
+        *self = resized;
+    }
+}
+
+pub fn color_to_rgba(color: Color) -> [u8; 4] {
+    [color.r(), color.g(), color.b(), color.a()].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+pub fn rgba_to_color([r, g, b, a]: [u8; 4]) -> Color {
+    Color::from_int_rgba(r, g, b, a)
+}
+
+pub mod rgba {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use speedy2d::color::Color;
+
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        super::color_to_rgba(*color).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        <[u8; 4]>::deserialize(deserializer).map(super::rgba_to_color)
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::game::{Game, RecordRequest};
+use crate::record::Recording;
+use crate::screenshot::{self, Screenshot};
+use crate::timestep;
+
+// `--headless [--script=commands.txt] [--output=wall.png] [--frames=dir]` runs the command
+// stream through a `Game` without opening a window, one line per frame. Images are what the
+// window would show at the configured size, screenshots and recordings are taken from them.
+pub fn run(config: Config) -> i32 {
+    let step = timestep::period(config.update_hz);
+    let mut folder = Screenshot::new(config.screenshot_folder.clone());
+    let mut game = Game::new(config);
+    if !crate::load_input(&mut game) {
+        return 1;
+    }
+
+    let lines: Box<dyn Iterator<Item = String>> = match crate::arg_value("--script") {
+        Some(path) => match fs::read_to_string(&path) {
+            Ok(script) => Box::new(
+                script
+                    .lines()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            ),
+            Err(err) => {
+                eprintln!("Couldn't read script {path}: {err}");
+                return 1;
+            }
+        },
+        None => Box::new(BufReader::new(io::stdin()).lines().map_while(Result::ok)),
+    };
+    let frames = crate::arg_value("--frames").map(PathBuf::from);
+    if let Some(frames) = &frames {
+        if let Err(err) = fs::create_dir_all(frames) {
+            eprintln!("Couldn't create {}: {err}", frames.display());
+            return 1;
+        }
+    }
+
+    let mut recording = None;
+    let mut failed = false;
+    let mut current_frame = 0;
+    for line in lines {
+        game.apply_command(&line);
+        game.update(step);
+        let frame = game.frame();
+        failed |= !take_screenshots(&mut game, &mut folder, &frame);
+        for request in game.take_recordings() {
+            let request = match request {
+                RecordRequest::Start(path, every) => Some((PathBuf::from(path), every)),
+                RecordRequest::Stop => None,
+                RecordRequest::Toggle if recording.is_some() => None,
+                RecordRequest::Toggle => match folder.next_path("gif") {
+                    Ok(path) => Some((path, 1)),
+                    Err(err) => {
+                        game.report(format!("Recording failed: {err:#}"));
+                        failed = true;
+                        continue;
+                    }
+                },
+            };
+            failed |= !set_recording(&mut game, &mut recording, request, step);
+        }
+        let stopped = recording.as_ref().is_some_and(|recording: &Recording| {
+            recording.capture(current_frame, || frame.clone()).is_err()
+        });
+        if stopped {
+            set_recording(&mut game, &mut recording, None, step);
+            failed = true;
+        }
+        if let Some(frames) = &frames {
+            if !write_image(&frame, &frames.join(format!("{current_frame:05}.png"))) {
+                return 1;
+            }
+        }
+        current_frame += 1;
+    }
+    game.update(step);
+    failed |= !set_recording(&mut game, &mut recording, None, step);
+
+    let output = crate::arg_value("--output").unwrap_or_else(|| "wall.png".to_string());
+    if write_image(&game.frame(), Path::new(&output)) && !failed {
+        0
+    } else {
+        1
+    }
+}
+
+// Returns false if any of them failed, like the app it reports and carries on.
+fn take_screenshots(game: &mut Game, folder: &mut Screenshot, frame: &RgbaImage) -> bool {
+    let mut saved = true;
+    for request in game.take_screenshots() {
+        let path = match request.path {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => folder.next_path(request.format.extension()),
+        };
+        let crop = request
+            .region
+            .map(|region| {
+                game.screen_area(region)
+                    .ok_or_else(|| anyhow!("the region isn't on screen"))
+            })
+            .transpose();
+        let result = path.and_then(|path| {
+            let image = screenshot::cropped(frame.clone(), crop?);
+            screenshot::save(image, request.format, &path).map(|()| path)
+        });
+        match result {
+            Ok(path) => game.report(format!("Saved screenshot {}", path.display())),
+            Err(err) => {
+                game.report(format!("Screenshot failed: {err:#}"));
+                saved = false;
+            }
+        }
+    }
+    saved
+}
+
+// Finishes the current recording and starts the next one if there is one, false on errors.
+fn set_recording(
+    game: &mut Game,
+    recording: &mut Option<Recording>,
+    next: Option<(PathBuf, u64)>,
+    step: Duration,
+) -> bool {
+    let mut ok = true;
+    if let Some(finished) = recording.take() {
+        let path = finished.path.display().to_string();
+        match finished.finish() {
+            Ok(frames) => game.report(format!("Recorded {frames} frames to {path}")),
+            Err(err) => {
+                game.report(format!("Recording failed: {err:#}"));
+                ok = false;
+            }
+        }
+    }
+    if let Some((path, every)) = next {
+        match Recording::start(&path, every, step) {
+            Ok(started) => {
+                game.report(format!("Recording to {}", path.display()));
+                *recording = Some(started);
+            }
+            Err(err) => {
+                game.report(format!("Recording failed: {err:#}"));
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+fn write_image(image: &RgbaImage, path: &Path) -> bool {
+    match image.save(path) {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!("Couldn't write {}: {err}", path.display());
+            false
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use speedy2d::window::{ModifiersState, VirtualKeyCode};
+
+use std::str::FromStr;
+
+use crate::app::Keyboard;
+
+// `global` is always on, `wall` and `editor` depend on F2, `client` drives the TCP client.
+// While text is being typed only `global` chords fire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Global,
+    Wall,
+    Editor,
+    Client,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "global" => Ok(Self::Global),
+            "wall" => Ok(Self::Wall),
+            "editor" => Ok(Self::Editor),
+            "client" => Ok(Self::Client),
+            other => Err(format!(
+                "unknown mode `{other}`, modes are global, wall, editor and client"
+            )),
+        }
+    }
+}
+
+// A key with the exact modifiers held, written like `Ctrl+Shift+S`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chord {
+    key: VirtualKeyCode,
+    ctrl: bool,
+    alt: bool,
+    shift: bool,
+    logo: bool,
+}
+
+impl Chord {
+    const fn new(key: VirtualKeyCode) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            alt: false,
+            shift: false,
+            logo: false,
+        }
+    }
+
+    const fn ctrl(key: VirtualKeyCode) -> Self {
+        Self {
+            ctrl: true,
+            ..Self::new(key)
+        }
+    }
+
+    fn matches(self, key: VirtualKeyCode, modifiers: &ModifiersState) -> bool {
+        self.key == key
+            && self.ctrl == modifiers.ctrl()
+            && self.alt == modifiers.alt()
+            && self.shift == modifiers.shift()
+            && self.logo == modifiers.logo()
+    }
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+        // Digits are `Key0` to `Key9` in `VirtualKeyCode`.
+        let name = if key.len() == 1 && key.chars().all(|ch| ch.is_ascii_digit()) {
+            format!("Key{key}")
+        } else {
+            key.to_string()
+        };
+        let key = serde_json::from_value(serde_json::Value::String(name))
+            .map_err(|_| format!("unknown key `{key}`"))?;
+        let mut chord = Self::new(key);
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl = true,
+                "alt" => chord.alt = true,
+                "shift" => chord.shift = true,
+                "logo" | "super" | "cmd" => chord.logo = true,
+                _ => return Err(format!("unknown modifier `{modifier}`")),
+            }
+        }
+        Ok(chord)
+    }
+}
+
+// Key chords mapped to lines of the command language, `bind.<mode>.<chord>=<command>` in the
+// config. Config lines replace the default for the same chord, an empty command unbinds it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<(Mode, Chord, String)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use VirtualKeyCode as Key;
+        let bindings = [
+            (Mode::Global, Chord::new(Key::Escape), "quit"),
+            (Mode::Global, Chord::new(Key::F1), "screenshot"),
+            (Mode::Global, Chord::new(Key::F2), "edit"),
+            (Mode::Global, Chord::new(Key::F3), "record"),
+            (Mode::Global, Chord::new(Key::F4), "stats"),
+            (Mode::Global, Chord::ctrl(Key::Equals), "zoom in"),
+            (Mode::Global, Chord::ctrl(Key::NumpadAdd), "zoom in"),
+            (Mode::Global, Chord::ctrl(Key::Minus), "zoom out"),
+            (Mode::Global, Chord::ctrl(Key::NumpadSubtract), "zoom out"),
+            (Mode::Global, Chord::ctrl(Key::Key0), "zoom auto"),
+            (Mode::Global, Chord::ctrl(Key::Numpad0), "zoom auto"),
+            (Mode::Editor, Chord::new(Key::P), "tool pencil"),
+            (Mode::Editor, Chord::new(Key::L), "tool line"),
+            (Mode::Editor, Chord::new(Key::R), "tool rect"),
+            (Mode::Editor, Chord::new(Key::F), "tool fill"),
+            (Mode::Editor, Chord::new(Key::T), "tool text"),
+            (Mode::Editor, Chord::new(Key::I), "tool pick"),
+            (Mode::Editor, Chord::new(Key::Tab), "tool next"),
+            (Mode::Client, Chord::new(Key::U), "r"),
+            (Mode::Client, Chord::new(Key::O), "l"),
+            (Mode::Client, Chord::new(Key::Period), "u"),
+            (Mode::Client, Chord::new(Key::E), "d"),
+        ];
+        Self {
+            bindings: bindings
+                .into_iter()
+                .map(|(mode, chord, command)| (mode, chord, command.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    // `mode.chord` is the part of the config key after `bind.`.
+    pub fn parse(&mut self, binding: &str, command: &str) -> Result<(), String> {
+        let (mode, chord) = binding
+            .split_once('.')
+            .ok_or_else(|| "expected bind.<mode>.<chord>".to_string())?;
+        self.bind(mode.parse()?, chord.parse()?, command.trim());
+        Ok(())
+    }
+
+    fn bind(&mut self, mode: Mode, chord: Chord, command: &str) {
+        let existing = self
+            .bindings
+            .iter()
+            .position(|(bound, bound_chord, _)| *bound == mode && *bound_chord == chord);
+        match (existing, command.is_empty()) {
+            (Some(idx), true) => {
+                self.bindings.remove(idx);
+            }
+            (Some(idx), false) => self.bindings[idx].2 = command.to_string(),
+            (None, true) => (),
+            (None, false) => self.bindings.push((mode, chord, command.to_string())),
+        }
+    }
+
+    // Commands for the chords pressed this frame.
+    pub fn commands(&self, modes: &[Mode], keyboard: &Keyboard) -> Vec<String> {
+        keyboard
+            .just_pressed
+            .iter()
+            .filter_map(|key| self.find(modes, *key, &keyboard.modifiers))
+            .map(str::to_string)
+            .collect()
+    }
+
+    // The first binding of `mode` whose chord is held down, for clients polling the keyboard.
+    pub fn held(&self, mode: Mode, keyboard: &Keyboard) -> Option<&str> {
+        self.bindings
+            .iter()
+            .find(|(bound, chord, _)| {
+                *bound == mode
+                    && keyboard
+                        .pressed
+                        .iter()
+                        .any(|key| chord.matches(*key, &keyboard.modifiers))
+            })
+            .map(|(_, _, command)| command.as_str())
+    }
+
+    fn find(
+        &self,
+        modes: &[Mode],
+        key: VirtualKeyCode,
+        modifiers: &ModifiersState,
+    ) -> Option<&str> {
+        self.bindings
+            .iter()
+            .find(|(mode, chord, _)| modes.contains(mode) && chord.matches(key, modifiers))
+            .map(|(_, _, command)| command.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyboard(pressed: VirtualKeyCode, ctrl: bool) -> Keyboard {
+        let modifiers = serde_json::json!({
+            "ctrl": ctrl,
+            "alt": false,
+            "shift": false,
+            "logo": false,
+        });
+        Keyboard {
+            buffer: Vec::new(),
+            modifiers: serde_json::from_value(modifiers).unwrap(),
+            pressed: vec![pressed],
+            just_pressed: vec![pressed],
+            just_released: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parses_chords() {
+        let chord: Chord = "Ctrl + shift+S".parse().unwrap();
+        assert_eq!(
+            chord,
+            Chord {
+                shift: true,
+                ..Chord::ctrl(VirtualKeyCode::S)
+            }
+        );
+        assert_eq!("7".parse(), Ok(Chord::new(VirtualKeyCode::Key7)));
+        assert_eq!("Cmd+F12".parse::<Chord>().map(|chord| chord.logo), Ok(true));
+        assert!("Ctrl+Blah".parse::<Chord>().is_err());
+        assert!("Hyper+A".parse::<Chord>().is_err());
+        assert!("".parse::<Chord>().is_err());
+    }
+
+    #[test]
+    fn config_lines_rebind_and_unbind() {
+        let mut keymap = Keymap::default();
+        keymap.parse("wall.Ctrl+S", " save wall.json").unwrap();
+        keymap.parse("global.F4", "").unwrap();
+        assert!(keymap.parse("nowhere.F1", "quit").is_err());
+        assert!(keymap.parse("F1", "quit").is_err());
+
+        let save = keyboard(VirtualKeyCode::S, true);
+        let wall = [Mode::Global, Mode::Wall];
+        assert!(keymap.commands(&[Mode::Global], &save).is_empty());
+        assert_eq!(keymap.commands(&wall, &save), ["save wall.json"]);
+        let stats = keyboard(VirtualKeyCode::F4, false);
+        assert!(keymap.commands(&[Mode::Global], &stats).is_empty());
+    }
+
+    #[test]
+    fn modifiers_have_to_match_exactly() {
+        let keymap = Keymap::default();
+        let plain_zero = keyboard(VirtualKeyCode::Key0, false);
+        assert!(keymap.commands(&[Mode::Global], &plain_zero).is_empty());
+        let ctrl_zero = keyboard(VirtualKeyCode::Key0, true);
+        assert_eq!(keymap.commands(&[Mode::Global], &ctrl_zero), ["zoom auto"]);
+        let down = keyboard(VirtualKeyCode::E, false);
+        assert_eq!(keymap.held(Mode::Client, &down), Some("d"));
+    }
+}
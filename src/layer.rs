@@ -0,0 +1,196 @@
+use glam::UVec2;
+
+use crate::grid::{Grid, Tile};
+use crate::palette::Palette;
+
+pub const BACKGROUND: &str = "background";
+pub const CONTENT: &str = "content";
+pub const UI: &str = "ui";
+pub const CURSOR: &str = "cursor";
+
+// A sheet of tiles over or under the wall, `None` cells let the layers below show through.
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    size: UVec2,
+    cells: Vec<Option<Tile>>,
+}
+
+impl Layer {
+    pub fn new(name: &str, size: UVec2) -> Self {
+        Self {
+            name: name.to_string(),
+            visible: true,
+            size,
+            cells: vec![None; (size.x * size.y) as usize],
+        }
+    }
+
+    fn index(&self, position: UVec2) -> Option<usize> {
+        (position.x < self.size.x && position.y < self.size.y)
+            .then_some((position.y * self.size.x + position.x) as usize)
+    }
+
+    pub fn get(&self, position: UVec2) -> Option<&Tile> {
+        self.index(position)
+            .and_then(|idx| self.cells[idx].as_ref())
+    }
+
+    pub fn set(&mut self, position: UVec2, tile: Option<Tile>) {
+        if let Some(idx) = self.index(position) {
+            self.cells[idx] = tile;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(None);
+    }
+
+    fn resize(&mut self, size: UVec2) {
+        let mut resized = Self::new(&self.name, size);
+        resized.visible = self.visible;
+        for y in 0..size.y.min(self.size.y) {
+            for x in 0..size.x.min(self.size.x) {
+                let position = UVec2::new(x, y);
+                resized.set(position, self.get(position).copied());
+            }
+        }
+        *self = resized;
+    }
+}
+
+// Layers from bottom to top. The wall itself is the `content` layer, it stays a `Grid` so
+// saving and exporting only ever see the content. Its blank cells let the layers below show
+// through.
+pub struct Layers {
+    below: Vec<Layer>,
+    above: Vec<Layer>,
+    pub content_visible: bool,
+}
+
+impl Layers {
+    pub fn new(size: UVec2) -> Self {
+        Self {
+            below: vec![Layer::new(BACKGROUND, size)],
+            above: vec![Layer::new(UI, size), Layer::new(CURSOR, size)],
+            content_visible: true,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Layer> {
+        self.below
+            .iter()
+            .chain(self.above.iter())
+            .find(|layer| layer.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Layer> {
+        self.below
+            .iter_mut()
+            .chain(self.above.iter_mut())
+            .find(|layer| layer.name == name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let below = self.below.iter().map(|layer| layer.name.as_str());
+        let above = self.above.iter().map(|layer| layer.name.as_str());
+        below.chain([CONTENT]).chain(above).collect()
+    }
+
+    pub fn set_visible(&mut self, name: &str, visible: bool) -> bool {
+        if name == CONTENT {
+            self.content_visible = visible;
+            return true;
+        }
+        self.get_mut(name)
+            .map(|layer| layer.visible = visible)
+            .is_some()
+    }
+
+    pub fn is_visible(&self, name: &str) -> Option<bool> {
+        if name == CONTENT {
+            return Some(self.content_visible);
+        }
+        self.get(name).map(|layer| layer.visible)
+    }
+
+    // Keeps every layer the size of the content.
+    pub fn fit(&mut self, size: UVec2) {
+        for layer in self.below.iter_mut().chain(self.above.iter_mut()) {
+            if layer.size != size {
+                layer.resize(size);
+            }
+        }
+    }
+
+    // The topmost visible tile at `position`, or `None` when every layer is empty there. A
+    // blank content cell is only drawn when no layer below has a tile.
+    pub fn composite(&self, content: &Grid, position: UVec2) -> Option<Tile> {
+        let visible = |layer: &&Layer| layer.visible;
+        let above = self.above.iter().rev().filter(visible);
+        let below = self.below.iter().rev().filter(visible);
+        let content = self
+            .content_visible
+            .then(|| content.get(position))
+            .flatten();
+        let (blank, written) = match content {
+            Some(tile) if tile.is_blank() => (Some(tile), None),
+            tile => (None, tile),
+        };
+        above
+            .filter_map(|layer| layer.get(position))
+            .chain(written)
+            .chain(below.filter_map(|layer| layer.get(position)))
+            .chain(blank)
+            .next()
+            .copied()
+    }
+
+    pub fn recolor(&mut self, palette: &Palette) {
+        for layer in self.below.iter_mut().chain(self.above.iter_mut()) {
+            for tile in layer.cells.iter_mut().flatten() {
+                tile.recolor(palette);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: UVec2 = UVec2::new(4, 2);
+    const CELL: UVec2 = UVec2::new(1, 1);
+
+    fn ch(layers: &Layers, content: &Grid) -> Option<char> {
+        layers.composite(content, CELL).map(|tile| tile.ch)
+    }
+
+    #[test]
+    fn blank_content_shows_the_background() {
+        let mut layers = Layers::new(SIZE);
+        let mut content = Grid::new(SIZE.x, SIZE.y);
+        assert_eq!(ch(&layers, &content), Some(' '));
+
+        let background = layers.get_mut(BACKGROUND).unwrap();
+        background.set(CELL, Some(Tile::new('.')));
+        assert_eq!(ch(&layers, &content), Some('.'));
+
+        content.set(CELL, Tile::new('x'));
+        assert_eq!(ch(&layers, &content), Some('x'));
+        layers.set_visible(CONTENT, false);
+        assert_eq!(ch(&layers, &content), Some('.'));
+    }
+
+    #[test]
+    fn upper_layers_cover_the_content() {
+        let mut layers = Layers::new(SIZE);
+        let mut content = Grid::new(SIZE.x, SIZE.y);
+        content.set(CELL, Tile::new('x'));
+        layers.get_mut(UI).unwrap().set(CELL, Some(Tile::new('u')));
+        assert_eq!(ch(&layers, &content), Some('u'));
+        layers.set_visible(UI, false);
+        assert_eq!(ch(&layers, &content), Some('x'));
+        assert_eq!(layers.composite(&content, SIZE), None);
+    }
+}
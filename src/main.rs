@@ -9,27 +9,69 @@ use speedy2d::{
     Window,
 };
 
+mod ansi;
+mod apng;
 mod app;
+mod bytes;
+mod camera;
+mod cast;
 use app::App;
 
 mod config;
 use config::Config;
 
+mod cp437;
+mod editor;
+mod export;
 mod font;
 mod game;
+mod grid;
+mod headless;
+mod keymap;
+mod layer;
+mod palette;
+mod pane;
+mod pointer;
+mod raster;
+mod record;
+mod replay;
+mod save;
 mod screenshot;
+mod snapshot;
 mod spritesheet;
+mod stats;
+mod timestep;
+mod widget;
+mod xp;
 
 use std::io::{self, BufRead, BufReader};
+use std::path::Path;
 use std::sync::mpsc;
 use std::thread;
 
 fn main() {
+    if let Some(export) = arg_value("--export") {
+        std::process::exit(export_and_exit(&export));
+    }
+    if let Some(dir) = arg_value("--snapshot-test") {
+        let update = std::env::args().any(|arg| arg == "--update-snapshots");
+        let tolerance = arg_value("--tolerance").map_or(Some(2), |value| value.parse().ok());
+        let Some(tolerance) = tolerance else {
+            eprintln!("--tolerance expects a number from 0 to 255");
+            std::process::exit(1);
+        };
+        std::process::exit(snapshot::run(Path::new(&dir), update, tolerance));
+    }
+    if std::env::args().any(|arg| arg == "--headless") {
+        std::process::exit(headless::run(Config::new("config.txt")));
+    }
+    let config = Config::new("config.txt");
+    let keymap = config.keymap.clone();
     thread::Builder::new()
         .name("app_client thread".to_string())
         .spawn(move || {
             thread::sleep(std::time::Duration::from_millis(500));
-            start_client().unwrap();
+            start_client(&keymap).unwrap();
         });
     let (tx, rx) = mpsc::channel::<String>();
     thread::spawn(move || {
@@ -43,25 +85,60 @@ fn main() {
         }
     });
 
-    let config = Config::new("config.txt");
     let window_size = UVec2::new(config.window_width, config.window_height);
-    let window_pixels = WindowSize::PhysicalPixels(window_size);
+    // Scaled so the window keeps its size on HiDPI displays, where auto zoom enlarges the cells.
+    let window_pixels = WindowSize::ScaledPixels(window_size.as_vec2());
     let window = Window::new_with_options(
         &config.title,
         WindowCreationOptions::new_windowed(window_pixels, Some(WindowPosition::Center))
             .with_decorations(true)
-            .with_transparent(false),
+            .with_transparent(false)
+            .with_vsync(config.vsync),
     )
     .expect("Wasn't able to create a window!");
-    window.run_loop(App::new(window_size, config, rx));
+    let mut app = App::new(window_size, config, rx);
+    if let Some(path) = arg_value("--record-input") {
+        app.record_input(Path::new(&path));
+    }
+    if let Some(path) = arg_value("--replay") {
+        app.start_replay(Path::new(&path));
+    }
+    window.run_loop(app);
+}
+
+fn arg_value(name: &str) -> Option<String> {
+    std::env::args()
+        .skip(1)
+        .find_map(|arg| Some(arg.strip_prefix(name)?.strip_prefix('=')?.to_string()))
+}
+
+// `--export=out.html [--import=art.ans | --load=wall.json]` converts without opening a window.
+fn export_and_exit(export: &str) -> i32 {
+    let mut game = game::Game::new(Config::new("config.txt"));
+    if load_input(&mut game) && game.export(Path::new(export)) {
+        0
+    } else {
+        1
+    }
+}
+
+fn load_input(game: &mut game::Game) -> bool {
+    if let Some(path) = arg_value("--import") {
+        game.import(Path::new(&path))
+    } else if let Some(path) = arg_value("--load") {
+        game.load(Path::new(&path))
+    } else {
+        game.populate();
+        true
+    }
 }
 
 use crate::app::{AppRequest, Keyboard};
-use speedy2d::window::VirtualKeyCode;
+use crate::keymap::{Keymap, Mode};
 use std::io::{prelude::*, Write};
 use std::net::TcpStream;
 
-pub fn start_client() -> io::Result<()> {
+pub fn start_client(keymap: &Keymap) -> io::Result<()> {
     loop {
         let mut stream = TcpStream::connect("127.0.0.1:2434")?;
         let action = AppRequest::GetKeyboard;
@@ -75,15 +152,7 @@ pub fn start_client() -> io::Result<()> {
         stream.shutdown(std::net::Shutdown::Both)?;
         let mut stream = TcpStream::connect("127.0.0.1:2434")?;
         
-        let game_command = if kbd.pressed.contains(&VirtualKeyCode::U) {
-            "r"
-        } else if kbd.pressed.contains(&VirtualKeyCode::O) {
-            "l"
-        } else if kbd.pressed.contains(&VirtualKeyCode::Period) {
-            "u"
-        } else if kbd.pressed.contains(&VirtualKeyCode::E) {
-            "d"
-        } else { "w" };
+        let game_command = keymap.held(Mode::Client, &kbd).unwrap_or("w");
 		let action = AppRequest::Command(game_command.to_string());
         stream.write_all(serde_json::to_string(&action).unwrap().as_bytes())?;
         stream.shutdown(std::net::Shutdown::Both)?;
@@ -0,0 +1,122 @@
+use speedy2d::color::Color;
+
+use crate::grid::color_to_rgba;
+
+// DOS attribute order: black, blue, green, cyan, red, magenta, brown, light gray, then bright.
+pub const VGA16: [u32; 16] = [
+    0x00_0000, 0x00_00AA, 0x00_AA00, 0x00_AAAA, 0xAA_0000, 0xAA_00AA, 0xAA_5500, 0xAA_AAAA,
+    0x55_5555, 0x55_55FF, 0x55_FF55, 0x55_FFFF, 0xFF_5555, 0xFF_55FF, 0xFF_FF55, 0xFF_FFFF,
+];
+
+// Solarized mapped onto the same DOS order.
+pub const SOLARIZED: [u32; 16] = [
+    0x07_3642, 0x26_8BD2, 0x85_9900, 0x2A_A198, 0xDC_322F, 0xD3_3682, 0xB5_8900, 0xEE_E8D5,
+    0x00_2B36, 0x83_9496, 0x58_6E75, 0x93_A1A1, 0xCB_4B16, 0x6C_71C4, 0x65_7B83, 0xFD_F6E3,
+];
+
+// ANSI SGR colour number (red = 1, ...) to the DOS attribute index (red = 4, ...).
+pub const ANSI_TO_VGA: [usize; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+pub fn vga(idx: usize) -> Color {
+    Color::from_hex_rgb(VGA16[idx % VGA16.len()])
+}
+
+pub fn xterm(idx: u8) -> Color {
+    match idx {
+        0..=15 => vga(ANSI_TO_VGA[usize::from(idx % 8)] + usize::from(idx / 8) * 8),
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let idx = idx - 16;
+            Color::from_int_rgb(level(idx / 36), level(idx / 6 % 6), level(idx % 6))
+        }
+        232..=255 => {
+            let gray = 8 + (idx - 232) * 10;
+            Color::from_int_rgb(gray, gray, gray)
+        }
+    }
+}
+
+pub fn nearest(palette: &[u32], color: Color) -> usize {
+    let [r, g, b, _] = color_to_rgba(color).map(i32::from);
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, hex)| {
+            let (pr, pg, pb) = (
+                (*hex >> 16) as i32 & 0xFF,
+                (*hex >> 8) as i32 & 0xFF,
+                **hex as i32 & 0xFF,
+            );
+            (r - pr).pow(2) + (g - pg).pow(2) + (b - pb).pow(2)
+        })
+        .map_or(0, |(idx, _)| idx)
+}
+
+// Tiles may refer to palette entries by index, so swapping the palette recolours them.
+// Built-in palettes start with the 16 colours in DOS attribute order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<u32>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            name: "vga".to_string(),
+            colors: VGA16.to_vec(),
+        }
+    }
+}
+
+impl Palette {
+    pub fn builtin(name: &str) -> Option<Self> {
+        let colors = match name {
+            "vga" => VGA16.to_vec(),
+            "xterm" => VGA16
+                .iter()
+                .copied()
+                .chain((16..=255).map(|idx| {
+                    let [r, g, b, _] = color_to_rgba(xterm(idx));
+                    u32::from_be_bytes([0, r, g, b])
+                }))
+                .collect(),
+            "solarized" => SOLARIZED.to_vec(),
+            _ => return None,
+        };
+        Some(Self {
+            name: name.to_string(),
+            colors,
+        })
+    }
+
+    // Comma separated hex colours, `#` optional: `000000, #aa0000, ...`.
+    pub fn parse(name: &str, list: &str) -> Result<Self, String> {
+        let colors = list
+            .split(',')
+            .map(|hex| {
+                parse_hex(hex).ok_or_else(|| format!("`{}` is not a hex colour", hex.trim()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if colors.is_empty() || colors.len() > 256 {
+            return Err("a palette needs 1 to 256 colours".to_string());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            colors,
+        })
+    }
+
+    pub fn color(&self, idx: u8) -> Color {
+        self.colors
+            .get(usize::from(idx) % self.colors.len().max(1))
+            .map_or(Color::BLACK, |hex| Color::from_hex_rgb(*hex))
+    }
+}
+
+pub fn parse_hex(text: &str) -> Option<u32> {
+    let hex = text.trim().trim_start_matches('#');
+    (hex.len() == 6)
+        .then(|| u32::from_str_radix(hex, 16).ok())
+        .flatten()
+}
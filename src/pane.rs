@@ -0,0 +1,319 @@
+use glam::UVec2;
+use serde::{Deserialize, Serialize};
+
+use crate::ansi::Terminal;
+use crate::cp437::from_unicode_or_control;
+use crate::game::Cursor;
+use crate::palette::Palette;
+
+// The wall itself, with the editor, camera and layers. It can't be closed.
+pub const MAIN: u32 = 0;
+
+// Side by side borders use `│`, stacked ones `─`, joined where they meet. The mask has a
+// bit per neighbouring border cell: up, down, left and right from the lowest.
+const BORDER_GLYPHS: [(u8, char); 11] = [
+    (0b0011, '\u{b3}'),
+    (0b1100, '\u{c4}'),
+    (0b1111, '\u{c5}'),
+    (0b0111, '\u{b4}'),
+    (0b1011, '\u{c3}'),
+    (0b1101, '\u{c1}'),
+    (0b1110, '\u{c2}'),
+    (0b0101, '\u{d9}'),
+    (0b1001, '\u{c0}'),
+    (0b0110, '\u{bf}'),
+    (0b1010, '\u{da}'),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Placement {
+    Right,
+    Below,
+}
+
+// Screen cells covered by a pane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Area {
+    pub min: UVec2,
+    pub size: UVec2,
+}
+
+impl Area {
+    fn split(self, placement: Placement) -> (Self, Self, Self) {
+        let (axis, cross) = match placement {
+            Placement::Right => (UVec2::X, UVec2::Y),
+            Placement::Below => (UVec2::Y, UVec2::X),
+        };
+        let length = self.size.dot(axis);
+        let first = length.saturating_sub(1) / 2;
+        let second = length.saturating_sub(first + 1);
+        let part = |offset: u32, length: u32| Self {
+            min: self.min + axis * offset,
+            size: self.size * cross + axis * length,
+        };
+        (
+            part(0, first),
+            part(first, length.min(1)),
+            part(first + 1, second),
+        )
+    }
+
+    fn cells(self) -> impl Iterator<Item = UVec2> {
+        (0..self.size.y)
+            .flat_map(move |y| (0..self.size.x).map(move |x| self.min + UVec2::new(x, y)))
+    }
+}
+
+enum Node {
+    Pane(u32),
+    Split {
+        placement: Placement,
+        first: Box<Self>,
+        second: Box<Self>,
+    },
+}
+
+impl Node {
+    fn arrange(&self, area: Area, panes: &mut Vec<(u32, Area)>, borders: &mut Vec<UVec2>) {
+        match self {
+            Self::Pane(id) => panes.push((*id, area)),
+            Self::Split {
+                placement,
+                first,
+                second,
+            } => {
+                let (first_area, border, second_area) = area.split(*placement);
+                first.arrange(first_area, panes, borders);
+                borders.extend(border.cells());
+                second.arrange(second_area, panes, borders);
+            }
+        }
+    }
+
+    fn split(&mut self, id: u32, new: u32, placement: Placement) -> bool {
+        match self {
+            Self::Pane(pane) if *pane == id => {
+                *self = Self::Split {
+                    placement,
+                    first: Box::new(Self::Pane(id)),
+                    second: Box::new(Self::Pane(new)),
+                };
+                true
+            }
+            Self::Pane(_) => false,
+            Self::Split { first, second, .. } => {
+                first.split(id, new, placement) || second.split(id, new, placement)
+            }
+        }
+    }
+
+    // Replaces the split holding pane `id` with its other half.
+    fn remove(&mut self, id: u32) -> bool {
+        let Self::Split { first, second, .. } = self else {
+            return false;
+        };
+        let remaining = match (&**first, &**second) {
+            (Self::Pane(pane), _) if *pane == id => std::mem::replace(&mut **second, Self::Pane(0)),
+            (_, Self::Pane(pane)) if *pane == id => std::mem::replace(&mut **first, Self::Pane(0)),
+            _ => return first.remove(id) || second.remove(id),
+        };
+        *self = remaining;
+        true
+    }
+}
+
+// A terminal screen next to the wall. It is fed lines of text or ANSI, and `pane <id> ...`
+// moves its own cursor and writes to its grid.
+pub struct Pane {
+    pub id: u32,
+    pub terminal: Terminal,
+    pub cursor: Cursor,
+}
+
+impl Pane {
+    pub fn write_line(&mut self, text: &str) {
+        let chars = text.chars().chain("\r\n".chars());
+        self.terminal.feed(chars.map(from_unicode_or_control));
+    }
+
+    pub fn clear(&mut self) {
+        self.terminal.feed("\x1b[2J".chars());
+    }
+}
+
+pub struct Panes {
+    root: Node,
+    open: Vec<Pane>,
+    next_id: u32,
+}
+
+impl Panes {
+    pub const fn new() -> Self {
+        Self {
+            root: Node::Pane(MAIN),
+            open: Vec::new(),
+            next_id: MAIN + 1,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Pane> {
+        self.open.iter()
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut Pane> {
+        self.open.iter_mut().find(|pane| pane.id == id)
+    }
+
+    pub fn ids(&self) -> Vec<u32> {
+        std::iter::once(MAIN)
+            .chain(self.open.iter().map(|pane| pane.id))
+            .collect()
+    }
+
+    // Returns the id of the new pane.
+    pub fn split(&mut self, id: u32, placement: Placement) -> Option<u32> {
+        let new = self.next_id;
+        if !self.root.split(id, new, placement) {
+            return None;
+        }
+        self.next_id += 1;
+        self.open.push(Pane {
+            id: new,
+            terminal: Terminal::fixed(1, 1),
+            cursor: Cursor::default(),
+        });
+        Some(new)
+    }
+
+    pub fn close(&mut self, id: u32) -> bool {
+        if id == MAIN || !self.root.remove(id) {
+            return false;
+        }
+        self.open.retain(|pane| pane.id != id);
+        true
+    }
+
+    // Where every pane goes in a `view` sized window, and the border cells between them.
+    pub fn arrange(&self, view: UVec2) -> (Vec<(u32, Area)>, Vec<UVec2>) {
+        let (mut panes, mut borders) = (Vec::new(), Vec::new());
+        let area = Area {
+            min: UVec2::ZERO,
+            size: view,
+        };
+        self.root.arrange(area, &mut panes, &mut borders);
+        (panes, borders)
+    }
+
+    pub fn area(&self, id: u32, view: UVec2) -> Option<Area> {
+        let (panes, _) = self.arrange(view);
+        panes
+            .into_iter()
+            .find_map(|(pane, area)| (pane == id).then_some(area))
+    }
+
+    // Keeps every terminal the size of its pane.
+    pub fn fit(&mut self, view: UVec2) {
+        for (id, area) in self.arrange(view).0 {
+            if let Some(pane) = self.get_mut(id) {
+                if pane.terminal.grid().size() != area.size.max(UVec2::ONE) {
+                    pane.terminal.resize(area.size.x, area.size.y);
+                }
+            }
+        }
+    }
+
+    pub fn recolor(&mut self, palette: &Palette) {
+        for pane in &mut self.open {
+            pane.terminal.recolor(palette);
+            pane.cursor.recolor(palette);
+        }
+    }
+}
+
+// Box drawing glyph for a border cell, picked by which neighbours are borders too.
+pub fn border_glyph(cell: UVec2, borders: &[UVec2]) -> char {
+    let is_border = |x: i64, y: i64| {
+        u32::try_from(x)
+            .ok()
+            .zip(u32::try_from(y).ok())
+            .is_some_and(|(x, y)| borders.contains(&UVec2::new(x, y)))
+    };
+    let (x, y) = (i64::from(cell.x), i64::from(cell.y));
+    let mask = u8::from(is_border(x, y - 1))
+        | u8::from(is_border(x, y + 1)) << 1
+        | u8::from(is_border(x - 1, y)) << 2
+        | u8::from(is_border(x + 1, y)) << 3;
+    // Ends of a border only have a neighbour on one side.
+    let straight = if mask & 0b1100 == 0 {
+        '\u{b3}'
+    } else {
+        '\u{c4}'
+    };
+    BORDER_GLYPHS
+        .iter()
+        .find_map(|(glyph_mask, glyph)| (*glyph_mask == mask).then_some(*glyph))
+        .unwrap_or(straight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(x: u32, y: u32, width: u32, height: u32) -> Area {
+        Area {
+            min: UVec2::new(x, y),
+            size: UVec2::new(width, height),
+        }
+    }
+
+    #[test]
+    fn split_leaves_one_border_cell() {
+        let mut panes = Panes::new();
+        let right = panes.split(MAIN, Placement::Right).unwrap();
+        let (areas, borders) = panes.arrange(UVec2::new(11, 4));
+        assert_eq!(areas, [(MAIN, area(0, 0, 5, 4)), (right, area(6, 0, 5, 4))]);
+        let column: Vec<UVec2> = (0..4).map(|y| UVec2::new(5, y)).collect();
+        assert_eq!(borders, column);
+    }
+
+    #[test]
+    fn nested_splits_and_closing() {
+        let mut panes = Panes::new();
+        let right = panes.split(MAIN, Placement::Right).unwrap();
+        let below = panes.split(right, Placement::Below).unwrap();
+        assert_eq!(panes.ids(), [MAIN, right, below]);
+        assert_eq!(panes.area(below, UVec2::new(11, 5)), Some(area(6, 3, 5, 2)));
+        assert_eq!(panes.split(99, Placement::Below), None);
+
+        assert!(!panes.close(MAIN));
+        assert!(panes.close(right));
+        assert_eq!(panes.ids(), [MAIN, below]);
+        assert_eq!(panes.area(below, UVec2::new(11, 5)), Some(area(6, 0, 5, 5)));
+    }
+
+    #[test]
+    fn fit_resizes_terminals() {
+        let mut panes = Panes::new();
+        let right = panes.split(MAIN, Placement::Right).unwrap();
+        panes.fit(UVec2::new(21, 6));
+        let pane = panes.get_mut(right).unwrap();
+        assert_eq!(pane.terminal.grid().size(), UVec2::new(10, 6));
+    }
+
+    #[test]
+    fn border_glyphs_join() {
+        // A vertical border with a horizontal one branching off to the right.
+        let borders: Vec<UVec2> = (0..3)
+            .map(|y| UVec2::new(1, y))
+            .chain([UVec2::new(2, 1), UVec2::new(3, 1)])
+            .collect();
+        let glyph = |x, y| border_glyph(UVec2::new(x, y), &borders);
+        assert_eq!(glyph(1, 0), '\u{b3}');
+        assert_eq!(glyph(1, 1), '\u{c3}');
+        assert_eq!(glyph(1, 2), '\u{b3}');
+        assert_eq!(glyph(2, 1), '\u{c4}');
+        assert_eq!(glyph(3, 1), '\u{c4}');
+        let corner = [UVec2::new(0, 0), UVec2::new(1, 0), UVec2::new(0, 1)];
+        assert_eq!(border_glyph(UVec2::ZERO, &corner), '\u{da}');
+    }
+}
@@ -0,0 +1,146 @@
+use glam::UVec2;
+use serde::Serialize;
+use speedy2d::window::MouseButton;
+
+use crate::app::Mouse;
+
+// Oldest events are dropped when nobody collects them.
+const MAX_EVENTS: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+}
+
+impl Button {
+    const ALL: [Self; 3] = [Self::Left, Self::Middle, Self::Right];
+
+    const fn mouse_button(self) -> MouseButton {
+        match self {
+            Self::Left => MouseButton::Left,
+            Self::Middle => MouseButton::Middle,
+            Self::Right => MouseButton::Right,
+        }
+    }
+}
+
+// Mouse input in grid cells, what `AppRequest::MouseEvents` and `mouse on` report.
+#[derive(Clone, Debug, Serialize)]
+pub enum CellEvent {
+    Hover {
+        x: u32,
+        y: u32,
+    },
+    Press {
+        x: u32,
+        y: u32,
+        button: Button,
+    },
+    // `from` is where the button went down.
+    Drag {
+        x: u32,
+        y: u32,
+        button: Button,
+        from: [u32; 2],
+    },
+    Release {
+        x: u32,
+        y: u32,
+        button: Button,
+    },
+    // Released on the cell it was pressed on.
+    Click {
+        x: u32,
+        y: u32,
+        button: Button,
+    },
+    Scroll {
+        x: u32,
+        y: u32,
+        lines: f64,
+    },
+}
+
+// Turns the mouse into cell events. A button pressed outside the wall is ignored until it
+// comes back up, releasing outside reports the last cell the mouse was over.
+pub struct Pointer {
+    cell: Option<UVec2>,
+    // Cell each held button went down on.
+    held: [Option<UVec2>; 3],
+    was_pressed: [bool; 3],
+    events: Vec<CellEvent>,
+    // Prints events to stdout as JSON lines for the program feeding stdin.
+    pub echo: bool,
+}
+
+impl Pointer {
+    pub const fn new() -> Self {
+        Self {
+            cell: None,
+            held: [None; 3],
+            was_pressed: [false; 3],
+            events: Vec::new(),
+            echo: false,
+        }
+    }
+
+    pub fn take_events(&mut self) -> Vec<CellEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // `cell` is the grid cell under the mouse, if any.
+    pub fn input(&mut self, cell: Option<UVec2>, mouse: &Mouse) {
+        let last = cell.or(self.cell);
+        if let Some(cell) = cell.filter(|cell| self.cell != Some(*cell)) {
+            let mut dragged = false;
+            for (button, from) in Button::ALL.into_iter().zip(self.held) {
+                if let Some(from) = from {
+                    let from = from.to_array();
+                    self.push(cell, |x, y| CellEvent::Drag { x, y, button, from });
+                    dragged = true;
+                }
+            }
+            if !dragged {
+                self.push(cell, |x, y| CellEvent::Hover { x, y });
+            }
+        }
+        self.cell = cell;
+
+        for (idx, button) in Button::ALL.into_iter().enumerate() {
+            let pressed = mouse.is_pressed(button.mouse_button());
+            let just_pressed = pressed && !self.was_pressed[idx];
+            self.was_pressed[idx] = pressed;
+            match (self.held[idx], cell, last) {
+                (None, Some(cell), _) if just_pressed => {
+                    self.held[idx] = Some(cell);
+                    self.push(cell, |x, y| CellEvent::Press { x, y, button });
+                }
+                (Some(from), _, Some(last)) if !pressed => {
+                    self.held[idx] = None;
+                    self.push(last, |x, y| CellEvent::Release { x, y, button });
+                    if from == last {
+                        self.push(last, |x, y| CellEvent::Click { x, y, button });
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let lines = mouse.scroll_lines();
+        if let Some(cell) = cell.filter(|_| lines != 0.0) {
+            self.push(cell, |x, y| CellEvent::Scroll { x, y, lines });
+        }
+    }
+
+    fn push(&mut self, cell: UVec2, event: impl FnOnce(u32, u32) -> CellEvent) {
+        let event = event(cell.x, cell.y);
+        if self.echo {
+            println!("{}", serde_json::to_string(&event).unwrap());
+        }
+        self.events.push(event);
+        let excess = self.events.len().saturating_sub(MAX_EVENTS);
+        self.events.drain(..excess);
+    }
+}
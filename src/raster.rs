@@ -0,0 +1,142 @@
+use glam::{UVec2, Vec2};
+use image::{Rgba, RgbaImage};
+use speedy2d::color::Color;
+
+use crate::font::Font;
+use crate::grid::{color_to_rgba, Attributes, Grid, Tile};
+
+// What `Game::draw` needs from a backend, the window's `Graphics2D` or an image. Rectangles
+// go from the top left corner `min` to the bottom right `max`, in pixels.
+pub trait Canvas {
+    fn fill(&mut self, min: Vec2, max: Vec2, color: Color);
+    // Only the glyph's lit pixels, the background is filled first.
+    fn glyph(&mut self, ch: char, min: Vec2, max: Vec2, color: Color);
+}
+
+// CPU counterpart of the window, for rendering without a GL context.
+pub struct Image<'a> {
+    pub image: RgbaImage,
+    font: &'a Font,
+}
+
+impl<'a> Image<'a> {
+    pub fn new(size: UVec2, background: Color, font: &'a Font) -> Self {
+        let background = Rgba(color_to_rgba(background));
+        Self {
+            image: RgbaImage::from_pixel(size.x, size.y, background),
+            font,
+        }
+    }
+
+    // Pixels inside the rectangle, clipped to the image.
+    fn pixels(&self, min: Vec2, max: Vec2) -> (UVec2, UVec2) {
+        let size = UVec2::from(self.image.dimensions()).as_vec2();
+        let clip = |corner: Vec2| corner.round().clamp(Vec2::ZERO, size).as_uvec2();
+        (clip(min), clip(max))
+    }
+}
+
+impl Canvas for Image<'_> {
+    fn fill(&mut self, min: Vec2, max: Vec2, color: Color) {
+        let (from, to) = self.pixels(min, max);
+        let color = Rgba(color_to_rgba(color));
+        for y in from.y..to.y {
+            for x in from.x..to.x {
+                self.image.put_pixel(x, y, color);
+            }
+        }
+    }
+
+    fn glyph(&mut self, ch: char, min: Vec2, max: Vec2, color: Color) {
+        let (from, to) = self.pixels(min, max);
+        let origin = min.round();
+        // Glyph bits and lines per pixel.
+        let font_size = UVec2::new(8, self.font.height).as_vec2();
+        let scale = font_size / (max - min).round().max(Vec2::ONE);
+        let color = Rgba(color_to_rgba(color));
+        let glyph = self.font.glyph(u32::from(ch));
+        for y in from.y..to.y {
+            for x in from.x..to.x {
+                let bit = ((UVec2::new(x, y).as_vec2() - origin) * scale).as_uvec2();
+                let line = glyph.get(bit.y as usize).copied().unwrap_or(0);
+                if line & (1 << bit.x.min(7)) != 0 {
+                    self.image.put_pixel(x, y, color);
+                }
+            }
+        }
+    }
+}
+
+// A tile at `position`, `zoom` thickens the underline along with the glyph.
+pub fn draw_tile(
+    canvas: &mut impl Canvas,
+    tile: &Tile,
+    position: Vec2,
+    size: Vec2,
+    zoom: f32,
+    blink_on: bool,
+) {
+    let (fg, bg) = tile.shown_colors(blink_on);
+    canvas.fill(position, position + size, bg);
+    canvas.glyph(tile.ch, position, position + size, fg);
+    if tile.attributes.contains(Attributes::UNDERLINE) {
+        let min = position + Vec2::new(0.0, size.y - 2.0 * zoom);
+        let max = position + Vec2::new(size.x, size.y - zoom);
+        canvas.fill(min, max, fg);
+    }
+}
+
+// Just the content grid at its own size, `Game::frame` has everything the window shows.
+pub fn render(grid: &Grid, font: &Font, cell_size: UVec2, blink_on: bool) -> RgbaImage {
+    let mut canvas = Image::new(grid.size() * cell_size, Color::BLACK, font);
+    for (y, row) in grid.rows().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            let position = (UVec2::new(x as u32, y as u32) * cell_size).as_vec2();
+            let size = cell_size.as_vec2();
+            draw_tile(&mut canvas, tile, position, size, 1.0, blink_on);
+        }
+    }
+    canvas.image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_fill_their_cell() {
+        let font = Font::vga8();
+        let mut grid = Grid::new(2, 1);
+        let red = Color::from_rgb(1.0, 0.0, 0.0);
+        grid.set(UVec2::ZERO, Tile::new('\u{db}').with_fg(red));
+        let underlined = Tile::new(' ').with_attributes(Attributes::UNDERLINE);
+        grid.set(UVec2::X, underlined);
+        let image = render(&grid, &font, UVec2::new(8, 16), true);
+        assert_eq!(image.dimensions(), (16, 16));
+
+        let red = Rgba(color_to_rgba(red));
+        assert!((0..16).all(|y| (0..8).all(|x| *image.get_pixel(x, y) == red)));
+        let (fg, bg) = underlined.shown_colors(true);
+        let (fg, bg) = (Rgba(color_to_rgba(fg)), Rgba(color_to_rgba(bg)));
+        for y in 0..16 {
+            let expected = if y == 14 { fg } else { bg };
+            assert!((8..16).all(|x| *image.get_pixel(x, y) == expected));
+        }
+    }
+
+    #[test]
+    fn drawing_is_clipped_to_the_image() {
+        let font = Font::vga8();
+        let mut canvas = Image::new(UVec2::new(4, 4), Color::BLACK, &font);
+        let white = Color::WHITE;
+        canvas.fill(Vec2::new(2.0, -3.0), Vec2::new(9.0, 2.0), white);
+        canvas.glyph('\u{db}', Vec2::new(-6.0, 3.0), Vec2::new(2.0, 19.0), white);
+        let lit: Vec<(u32, u32)> = canvas
+            .image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel == Rgba(color_to_rgba(white)))
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(lit, [(2, 0), (3, 0), (2, 1), (3, 1), (0, 3), (1, 3)]);
+    }
+}
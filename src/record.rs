@@ -0,0 +1,209 @@
+use anyhow::{anyhow, bail, Context, Result};
+use image::{imageops, Rgba, RgbaImage};
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::apng;
+use crate::screenshot::{self, Format};
+
+// Captured frames waiting for the encoder, the game loop blocks once this many are queued.
+const QUEUE: usize = 16;
+// 1 is the slowest and best quantisation, 30 the fastest.
+const GIF_SPEED: i32 = 10;
+
+enum Target {
+    Gif(BufWriter<File>),
+    Apng(apng::Encoder<BufWriter<File>>),
+    // Numbered PNGs in a folder.
+    Sequence(PathBuf),
+}
+
+// Frames are encoded on a separate thread, `finish` waits for it and returns its result.
+pub struct Recording {
+    pub path: PathBuf,
+    every: u64,
+    frames: SyncSender<RgbaImage>,
+    worker: JoinHandle<Result<u32>>,
+}
+
+impl Recording {
+    // A `.gif` path records an animated GIF, `.png` or `.apng` an animated PNG and a path
+    // without extension a folder of PNGs. `frame` is the time between two game loop frames.
+    pub fn start(path: &Path, every: u64, frame: Duration) -> Result<Self> {
+        let every = every.max(1);
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        let create = || {
+            File::create(path)
+                .map(BufWriter::new)
+                .with_context(|| format!("Couldn't create {}", path.display()))
+        };
+        let target = match extension.as_deref() {
+            Some("gif") => Target::Gif(create()?),
+            Some("png" | "apng") => Target::Apng(apng::Encoder::new(create()?)),
+            None => {
+                fs::create_dir_all(path)
+                    .with_context(|| format!("Couldn't create {}", path.display()))?;
+                Target::Sequence(path.to_path_buf())
+            }
+            Some(other) => bail!("Can't record to .{other}, use a .gif, a .png or a folder"),
+        };
+        let delay_ms = u32::try_from(frame.as_millis() * u128::from(every)).unwrap_or(u32::MAX);
+        let (frames, receiver) = mpsc::sync_channel(QUEUE);
+        let worker = thread::Builder::new()
+            .name("recording".to_string())
+            .spawn(move || encode(target, receiver, delay_ms))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            every,
+            frames,
+            worker,
+        })
+    }
+
+    // `grab` only runs on the frames that are recorded.
+    pub fn capture(&self, current_frame: u64, grab: impl FnOnce() -> RgbaImage) -> Result<()> {
+        if current_frame % self.every != 0 {
+            return Ok(());
+        }
+        self.push(grab())
+    }
+
+    // Fails once the encoder has stopped, `finish` has the reason.
+    pub fn push(&self, image: RgbaImage) -> Result<()> {
+        self.frames
+            .send(image)
+            .map_err(|_| anyhow!("The recording stopped early"))
+    }
+
+    // Encodes the queued frames and closes the file, returns the number of frames written.
+    pub fn finish(self) -> Result<u32> {
+        drop(self.frames);
+        self.worker
+            .join()
+            .map_err(|_| anyhow!("The recording thread panicked"))?
+    }
+}
+
+fn encode(target: Target, frames: Receiver<RgbaImage>, delay_ms: u32) -> Result<u32> {
+    let mut frames = frames.into_iter();
+    let Some(first) = frames.next() else {
+        bail!("No frames were recorded");
+    };
+    let size = first.dimensions();
+    let frames = iter::once(first).chain(frames.map(|image| fit(image, size)));
+    let mut count = 0;
+    match target {
+        Target::Gif(file) => {
+            let (Ok(width), Ok(height)) = (u16::try_from(size.0), u16::try_from(size.1)) else {
+                bail!("{}x{} is too big for a GIF", size.0, size.1);
+            };
+            let mut encoder = gif::Encoder::new(file, width, height, &[])?;
+            encoder.set_repeat(gif::Repeat::Infinite)?;
+            // GIF delays are in hundredths of a second.
+            let delay = u16::try_from((delay_ms + 5) / 10).unwrap_or(u16::MAX);
+            for image in frames {
+                let mut pixels = image.into_raw();
+                let mut frame = gif::Frame::from_rgba_speed(width, height, &mut pixels, GIF_SPEED);
+                frame.delay = delay;
+                encoder.write_frame(&frame)?;
+                count += 1;
+            }
+            encoder.into_inner()?.flush()?;
+        }
+        Target::Apng(mut encoder) => {
+            for image in frames {
+                encoder.encode_frame(&image, delay_ms)?;
+                count += 1;
+            }
+            encoder.finish()?;
+        }
+        Target::Sequence(folder) => {
+            for image in frames {
+                let path = folder.join(format!("{count:05}.png"));
+                screenshot::save(image, Format::Png, &path)?;
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+// The window can be resized while recording, later frames are cropped or padded to the first.
+fn fit(image: RgbaImage, (width, height): (u32, u32)) -> RgbaImage {
+    if image.dimensions() == (width, height) {
+        return image;
+    }
+    let mut canvas = RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 255]));
+    imageops::replace(&mut canvas, &image, 0, 0);
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifDecoder;
+    use image::codecs::png::PngDecoder;
+    use image::{AnimationDecoder, Frame};
+
+    // Every other frame at 20 ms, the second frame is bigger and gets cropped.
+    fn record(path: &Path) -> Result<u32> {
+        let recording = Recording::start(path, 2, Duration::from_millis(20))?;
+        for shade in 0..3 {
+            let width = if shade == 1 { 6 } else { 4 };
+            let pixel = Rgba([shade * 60, 0, 255 - shade * 60, 255]);
+            recording.push(RgbaImage::from_pixel(width, 3, pixel))?;
+        }
+        recording.finish()
+    }
+
+    fn check(frames: &[Frame]) {
+        assert_eq!(frames.len(), 3);
+        for frame in frames {
+            assert_eq!(frame.buffer().dimensions(), (4, 3));
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            assert_eq!(numer / denom, 40);
+        }
+    }
+
+    #[test]
+    fn gif_apng_and_sequence_keep_every_frame() {
+        let folder =
+            std::env::temp_dir().join(format!("grid_renderer-{}-record", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+
+        let gif = folder.join("out.gif");
+        assert_eq!(record(&gif).unwrap(), 3);
+        let decoder = GifDecoder::new(File::open(&gif).unwrap()).unwrap();
+        check(&decoder.into_frames().collect_frames().unwrap());
+
+        let apng = folder.join("out.png");
+        assert_eq!(record(&apng).unwrap(), 3);
+        let decoder = PngDecoder::new(File::open(&apng).unwrap()).unwrap();
+        assert!(decoder.is_apng());
+        let frames = decoder.apng().into_frames().collect_frames().unwrap();
+        check(&frames);
+        let last = frames[2].buffer().get_pixel(0, 0);
+        assert_eq!(last, &Rgba([120, 0, 135, 255]));
+
+        let sequence = folder.join("frames");
+        assert_eq!(record(&sequence).unwrap(), 3);
+        let last = image::open(sequence.join("00002.png")).unwrap().to_rgba8();
+        assert_eq!(last.dimensions(), (4, 3));
+        assert!(!sequence.join("00003.png").exists());
+
+        let empty = Recording::start(&folder.join("empty.gif"), 1, Duration::ZERO).unwrap();
+        assert!(empty.finish().is_err());
+        assert!(Recording::start(&folder.join("out.bmp"), 1, Duration::ZERO).is_err());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
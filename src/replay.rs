@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use speedy2d::window::{ModifiersState, MouseButton, VirtualKeyCode};
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::app::AppRequest;
+
+// Everything that reaches `App` from outside during a frame.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum InputEvent {
+    KeyDown(VirtualKeyCode),
+    KeyUp(VirtualKeyCode),
+    Char(char),
+    Modifiers(ModifiersState),
+    MouseMove([f32; 2]),
+    MouseDown(Button),
+    MouseUp(Button),
+    Scroll(f64),
+    Resize([u32; 2]),
+    ScaleFactor(f64),
+    // Fixed updates run this frame, only recorded when it isn't 1.
+    Updates(u32),
+    Stdin(String),
+    Request(AppRequest),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+    Back,
+    Forward,
+    Other(u16),
+}
+
+impl From<MouseButton> for Button {
+    fn from(button: MouseButton) -> Self {
+        match button {
+            MouseButton::Left => Self::Left,
+            MouseButton::Middle => Self::Middle,
+            MouseButton::Right => Self::Right,
+            MouseButton::Back => Self::Back,
+            MouseButton::Forward => Self::Forward,
+            MouseButton::Other(id) => Self::Other(id),
+        }
+    }
+}
+
+impl From<Button> for MouseButton {
+    fn from(button: Button) -> Self {
+        match button {
+            Button::Left => Self::Left,
+            Button::Middle => Self::Middle,
+            Button::Right => Self::Right,
+            Button::Back => Self::Back,
+            Button::Forward => Self::Forward,
+            Button::Other(id) => Self::Other(id),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    frame: u64,
+    event: InputEvent,
+}
+
+// One JSON object per line: `{"frame":12,"event":{"KeyDown":"A"}}`.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("Couldn't create {}", path.display()))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn record(&mut self, frame: u64, event: &InputEvent) -> Result<()> {
+        let entry = serde_json::to_string(&Entry {
+            frame,
+            event: event.clone(),
+        })?;
+        writeln!(self.writer, "{entry}")?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+pub struct InputReplay {
+    entries: VecDeque<Entry>,
+}
+
+impl InputReplay {
+    pub fn open(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        let entries = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(idx, line)| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("Invalid input event on line {}", idx + 1))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { entries })
+    }
+
+    // Events recorded for `frame`, in their original order.
+    pub fn events(&mut self, frame: u64) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        while let Some(entry) = self.entries.front() {
+            if entry.frame > frame {
+                break;
+            }
+            events.extend(self.entries.pop_front().map(|entry| entry.event));
+        }
+        events
+    }
+
+    pub fn finished(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Compared through JSON, the events don't implement `PartialEq`.
+    fn json(events: &[InputEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| serde_json::to_string(event).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn replay_gives_back_each_frame_of_a_recording() {
+        let path =
+            std::env::temp_dir().join(format!("grid_renderer-{}-input.jsonl", std::process::id()));
+        let session = [
+            (0, InputEvent::Resize([640, 480])),
+            (0, InputEvent::ScaleFactor(2.0)),
+            (1, InputEvent::KeyDown(VirtualKeyCode::F2)),
+            (1, InputEvent::MouseMove([12.5, 40.0])),
+            (3, InputEvent::Stdin("w".to_string())),
+            (4, InputEvent::KeyUp(VirtualKeyCode::F2)),
+        ];
+        let mut recorder = InputRecorder::create(&path).unwrap();
+        for (frame, event) in &session {
+            recorder.record(*frame, event).unwrap();
+        }
+        recorder.flush().unwrap();
+
+        let mut replay = InputReplay::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        for frame in 0..5 {
+            let expected: Vec<InputEvent> = session
+                .iter()
+                .filter(|(at, _)| *at == frame)
+                .map(|(_, event)| event.clone())
+                .collect();
+            assert!(!replay.finished());
+            assert_eq!(json(&replay.events(frame)), json(&expected));
+        }
+        assert!(replay.finished());
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bytes::Reader;
+use crate::game::Cursor;
+use crate::grid::{color_to_rgba, rgba_to_color, Attributes, Grid, Tile};
+
+// Version 2 added palette indices to the binary tiles.
+const VERSION: u32 = 2;
+const MAGIC: &[u8; 4] = b"WOTG";
+const TILE_BYTES: usize = 16;
+const FG_INDEXED: u8 = 1;
+const BG_INDEXED: u8 = 1 << 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Document {
+    pub version: u32,
+    pub grid: Grid,
+    pub cursor: Cursor,
+}
+
+impl Document {
+    pub const fn new(grid: Grid, cursor: Cursor) -> Self {
+        Self {
+            version: VERSION,
+            grid,
+            cursor,
+        }
+    }
+}
+
+pub fn save(path: &Path, document: &Document) -> Result<()> {
+    let bytes = if is_json(path) {
+        serde_json::to_vec_pretty(document)?
+    } else {
+        to_bytes(document)
+    };
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    // Write next to the target first, so a crash mid-save doesn't eat the previous file.
+    let tmp = temp_path(path);
+    fs::write(&tmp, bytes).with_context(|| format!("Couldn't write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Couldn't replace {}", path.display()))?;
+    Ok(())
+}
+
+// `wall.json.tmp`, keeping the whole name so `wall.json` and `wall.bin` don't share one.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+pub fn load(path: &Path) -> Result<Document> {
+    let bytes = fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    let document: Document = if bytes.starts_with(MAGIC) {
+        from_bytes(&bytes)?
+    } else {
+        serde_json::from_slice(&bytes)?
+    };
+    if document.version > VERSION {
+        bail!(
+            "{} has format version {}, newest supported is {VERSION}",
+            path.display(),
+            document.version
+        );
+    }
+    Ok(document)
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+fn to_bytes(document: &Document) -> Vec<u8> {
+    let Document { grid, cursor, .. } = document;
+    let mut bytes = Vec::with_capacity(32 + grid.tiles().len() * TILE_BYTES);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&grid.width().to_le_bytes());
+    bytes.extend_from_slice(&grid.height().to_le_bytes());
+    bytes.extend_from_slice(&cursor.x.to_le_bytes());
+    bytes.extend_from_slice(&cursor.y.to_le_bytes());
+    let cursor_tile = cursor.tile();
+    for tile in std::iter::once(&cursor_tile).chain(grid.tiles()) {
+        bytes.extend_from_slice(&u32::from(tile.ch).to_le_bytes());
+        bytes.extend_from_slice(&color_to_rgba(tile.fg));
+        bytes.extend_from_slice(&color_to_rgba(tile.bg));
+        bytes.push(tile.attributes.0);
+        let mut flags = 0;
+        if tile.fg_index.is_some() {
+            flags |= FG_INDEXED;
+        }
+        if tile.bg_index.is_some() {
+            flags |= BG_INDEXED;
+        }
+        bytes.extend_from_slice(&[
+            flags,
+            tile.fg_index.unwrap_or(0),
+            tile.bg_index.unwrap_or(0),
+        ]);
+    }
+    bytes
+}
+
+fn from_bytes(bytes: &[u8]) -> Result<Document> {
+    let mut reader = Reader::new(bytes, MAGIC.len());
+    let version = reader.u32()?;
+    let width = reader.u32()?;
+    let height = reader.u32()?;
+    let x = reader.u32()?;
+    let y = reader.u32()?;
+    let cursor_tile = read_tile(&mut reader, version)?;
+    let count = width
+        .checked_mul(height)
+        .with_context(|| format!("{width}x{height} grid is too big"))?;
+    let tiles = (0..count)
+        .map(|_| read_tile(&mut reader, version))
+        .collect::<Result<Vec<_>>>()?;
+    let grid = Grid::from_tiles(width, height, tiles).context("Tile count mismatch")?;
+    let cursor = Cursor::new(cursor_tile, x, y);
+    Ok(Document {
+        version,
+        grid,
+        cursor,
+    })
+}
+
+fn read_tile(reader: &mut Reader, version: u32) -> Result<Tile> {
+    let ch = char::from_u32(reader.u32()?).unwrap_or('?');
+    let fg = rgba_to_color(reader.take(4)?.try_into()?);
+    let bg = rgba_to_color(reader.take(4)?.try_into()?);
+    let attributes = Attributes(reader.take(1)?[0]);
+    let mut tile = Tile::new(ch)
+        .with_fg(fg)
+        .with_bg(bg)
+        .with_attributes(attributes);
+    if version >= 2 {
+        let [flags, fg_index, bg_index] = reader.take(3)?.try_into()?;
+        tile.fg_index = (flags & FG_INDEXED != 0).then_some(fg_index);
+        tile.bg_index = (flags & BG_INDEXED != 0).then_some(bg_index);
+    }
+    Ok(tile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+    use glam::UVec2;
+    use speedy2d::color::Color;
+
+    fn document() -> Document {
+        let palette = Palette::builtin("vga").unwrap();
+        let mut grid = Grid::new(3, 2);
+        grid.set(UVec2::new(1, 0), Tile::new('x').with_fg_index(12, &palette));
+        grid.set(
+            UVec2::new(2, 1),
+            Tile::new('\u{2592}')
+                .with_fg(Color::from_int_rgb(1, 2, 3))
+                .with_attributes(Attributes::BOLD),
+        );
+        Document::new(grid, Cursor::new(Tile::new('@'), 2, 1))
+    }
+
+    fn assert_same(loaded: &Document, saved: &Document) {
+        assert_eq!(loaded.version, VERSION);
+        assert_eq!(loaded.grid, saved.grid);
+        assert_eq!(loaded.cursor.tile(), saved.cursor.tile());
+        assert_eq!((loaded.cursor.x, loaded.cursor.y), (2, 1));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let saved = document();
+        assert_same(&from_bytes(&to_bytes(&saved)).unwrap(), &saved);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let saved = document();
+        let json = serde_json::to_vec(&saved).unwrap();
+        assert_same(&serde_json::from_slice(&json).unwrap(), &saved);
+    }
+
+    #[test]
+    fn saves_in_both_formats_side_by_side() {
+        let folder =
+            std::env::temp_dir().join(format!("grid_renderer-{}-save", std::process::id()));
+        let (json, bin) = (folder.join("wall.json"), folder.join("wall.bin"));
+        assert_ne!(temp_path(&json), temp_path(&bin));
+        let saved = document();
+        save(&json, &saved).unwrap();
+        save(&bin, &saved).unwrap();
+        assert_same(&load(&json).unwrap(), &saved);
+        assert_same(&load(&bin).unwrap(), &saved);
+        let mut names: Vec<_> = fs::read_dir(&folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["wall.bin", "wall.json"]);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn rejects_truncated_binary() {
+        let bytes = to_bytes(&document());
+        assert!(from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_overflowing_size() {
+        let mut bytes = to_bytes(&document());
+        bytes[8..16].copy_from_slice(&[0xFF; 8]);
+        assert!(from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_tile_count_mismatch() {
+        let mut json = serde_json::to_value(document()).unwrap();
+        json["grid"]["width"] = 4.into();
+        assert!(serde_json::from_value::<Document>(json).is_err());
+    }
+}
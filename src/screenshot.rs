@@ -1,66 +1,204 @@
-use anyhow::Result;
-use image::{ImageBuffer, Rgba};
+use anyhow::{bail, Context, Result};
+use glam::UVec2;
+use image::{imageops, DynamicImage, ImageBuffer, ImageOutputFormat, RgbaImage};
+use serde::{Deserialize, Serialize};
 use speedy2d::{image::ImageDataType, Graphics2D};
 use std::fs;
+use std::path::{Path, PathBuf};
 
-const CLEAN_FOLDER: bool = true;
 pub struct Screenshot {
     pub folder: String,
-    counter: u32,
+    counter: Option<u32>,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Format {
     Jpeg,
+    #[default]
     Png,
 }
 
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpeg",
+            Self::Png => "png",
+        }
+    }
+
+    const fn output(self) -> ImageOutputFormat {
+        match self {
+            Self::Jpeg => ImageOutputFormat::Jpeg(90),
+            Self::Png => ImageOutputFormat::Png,
+        }
+    }
+}
+
+// A rectangle of wall cells, cropped to the part shown in the main pane at capture time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    // `x,y,width,height`
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut numbers = text.split(',').map(str::parse::<u32>);
+        let region = Self {
+            x: numbers.next()?.ok()?,
+            y: numbers.next()?.ok()?,
+            width: numbers.next()?.ok()?,
+            height: numbers.next()?.ok()?,
+        };
+        numbers.next().is_none().then_some(region)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub path: Option<String>,
+    pub format: Format,
+    pub region: Option<Region>,
+}
+
+// `crop` is the top left pixel and size of the part to keep.
+pub fn grab(graphics: &mut Graphics2D, crop: Option<(UVec2, UVec2)>) -> RgbaImage {
+    let raw = graphics.capture(ImageDataType::RGBA);
+    let image: RgbaImage =
+        ImageBuffer::from_raw(raw.size().x, raw.size().y, raw.data().clone()).unwrap();
+    cropped(image, crop)
+}
+
+pub fn cropped(image: RgbaImage, crop: Option<(UVec2, UVec2)>) -> RgbaImage {
+    match crop {
+        Some((min, size)) => {
+            let width = size.x.min(image.width().saturating_sub(min.x));
+            let height = size.y.min(image.height().saturating_sub(min.y));
+            imageops::crop_imm(&image, min.x, min.y, width, height).to_image()
+        }
+        None => image,
+    }
+}
+
+pub fn encode(image: RgbaImage, format: Format) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    DynamicImage::ImageRgba8(image)
+        .write_to(&mut bytes, format.output())
+        .context("Couldn't encode screenshot")?;
+    Ok(bytes)
+}
+
+pub fn save(image: RgbaImage, format: Format, path: &Path) -> Result<()> {
+    fs::write(path, encode(image, format)?)
+        .with_context(|| format!("Couldn't write {}", path.display()))
+}
+
 impl Screenshot {
     pub const fn new(folder: String) -> Self {
-        Self { folder, counter: 0 }
+        Self {
+            folder,
+            counter: None,
+        }
     }
 
-    pub fn capture(&mut self, graphics: &mut Graphics2D, format: Format) {
-        if self.counter == 0 && CLEAN_FOLDER {
-            let path = fs::canonicalize(&self.folder).unwrap();
-            dbg!("Clearing screenshots folder: {}", path.display());
-            let _ = fs::remove_dir_all(path);
-        }
-        if self.folder_path().is_ok() {
-            let raw = graphics.capture(ImageDataType::RGBA);
-            let image: ImageBuffer<Rgba<u8>, _> =
-                ImageBuffer::from_raw(raw.size().x, raw.size().y, raw.data().as_slice()).unwrap();
-
-            let extension = match format {
-                Format::Jpeg => "jpeg",
-                Format::Png => "png",
-            };
-
-            image
-                .save(
-                    std::path::Path::new(".")
-                        .join(&self.folder)
-                        .join(format!("{}.{}", self.counter, extension)),
-                )
-                .unwrap();
-
-            self.counter += 1;
-        } else {
-            panic!("{:#?}", self.folder_path())
+    // Numbering continues after the highest numbered file already in the folder,
+    // so earlier captures are never overwritten.
+    pub fn next_path(&mut self, extension: &str) -> Result<PathBuf> {
+        self.folder_path()?;
+        let counter = match self.counter {
+            Some(counter) => counter,
+            None => self.next_free_number()?,
+        };
+        self.counter = Some(counter + 1);
+        Ok(Path::new(&self.folder).join(format!("{counter}.{extension}")))
+    }
+
+    fn next_free_number(&self) -> Result<u32> {
+        let mut next = 0;
+        for entry in fs::read_dir(&self.folder)
+            .with_context(|| format!("Couldn't read screenshots directory {}", self.folder))?
+        {
+            let path = entry?.path();
+            let number = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u32>().ok());
+            if let Some(number) = number {
+                next = next.max(number + 1);
+            }
         }
+        Ok(next)
     }
 
     fn folder_path(&self) -> Result<()> {
         if let Ok(entry) = fs::metadata(&self.folder) {
             if !entry.is_dir() {
-                return Err(anyhow::format_err!(
+                bail!(
                     "Couldn't create screenshots directory! Looks like path is occupied {}",
                     self.folder
-                ));
+                );
             }
         } else {
-            fs::create_dir(&self.folder)?;
+            fs::create_dir_all(&self.folder).with_context(|| {
+                format!("Couldn't create screenshots directory {}", self.folder)
+            })?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_need_four_numbers() {
+        let region = Region {
+            x: 1,
+            y: 2,
+            width: 30,
+            height: 4,
+        };
+        assert_eq!(Region::parse("1,2,30,4"), Some(region));
+        let counts = ["", "1,2,30", "1,2,30,4,5"];
+        let numbers = ["1,2,,4", "1, 2,30,4", "-1,2,30,4", "a,b,c,d"];
+        for text in counts.into_iter().chain(numbers) {
+            assert_eq!(Region::parse(text), None, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn numbering_continues_after_existing_files() {
+        let folder =
+            std::env::temp_dir().join(format!("grid_renderer-{}-shots", std::process::id()));
+        let mut screenshot = Screenshot::new(folder.display().to_string());
+        // A missing folder is created and starts at 0.
+        assert_eq!(screenshot.next_path("png").unwrap(), folder.join("0.png"));
+
+        for name in ["3.png", "7.jpeg", "notes.txt", "12a.png"] {
+            fs::write(folder.join(name), b"").unwrap();
+        }
+        let mut screenshot = Screenshot::new(folder.display().to_string());
+        assert_eq!(screenshot.next_path("png").unwrap(), folder.join("8.png"));
+        assert_eq!(screenshot.next_path("gif").unwrap(), folder.join("9.gif"));
+        fs::remove_dir_all(&folder).unwrap();
+
+        // A file where the folder should be is an error, and is left alone.
+        fs::write(&folder, b"").unwrap();
+        assert!(screenshot.next_path("png").is_err());
+        assert!(folder.is_file());
+        fs::remove_file(&folder).unwrap();
+    }
+}
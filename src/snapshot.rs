@@ -0,0 +1,215 @@
+use anyhow::{Context, Result};
+use glam::UVec2;
+use image::RgbaImage;
+use serde::Deserialize;
+
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::game::Game;
+use crate::grid::{Grid, Tile};
+use crate::raster;
+use crate::timestep;
+
+const SCRIPT: &str = "script.txt";
+const GRID: &str = "grid.json";
+const FRAME: &str = "frame.png";
+
+enum Outcome {
+    Passed,
+    Written,
+    Failed(Vec<String>),
+}
+
+// `grid.json` only lists the cells that aren't blank, one per line.
+#[derive(Deserialize)]
+struct GridSnapshot {
+    width: u32,
+    height: u32,
+    cells: Vec<(u32, u32, Tile)>,
+}
+
+impl GridSnapshot {
+    fn to_json(grid: &Grid) -> Result<String> {
+        let mut cells = Vec::new();
+        for (y, row) in (0..).zip(grid.rows()) {
+            for (x, tile) in (0..).zip(row) {
+                if !tile.is_blank() {
+                    cells.push(serde_json::to_string(&(x, y, tile))?);
+                }
+            }
+        }
+        Ok(format!(
+            "{{\n  \"width\": {},\n  \"height\": {},\n  \"cells\": [\n    {}\n  ]\n}}\n",
+            grid.width(),
+            grid.height(),
+            cells.join(",\n    ")
+        ))
+    }
+
+    fn into_grid(self) -> Grid {
+        let mut grid = Grid::new(self.width, self.height);
+        for (x, y, tile) in self.cells {
+            grid.set(UVec2::new(x, y), tile);
+        }
+        grid
+    }
+}
+
+// `--snapshot-test=snapshots [--update-snapshots] [--tolerance=2]`
+// Every folder under `dir` with a `script.txt` is a case. The script runs against a blank
+// `Game` one line per frame, with paths relative to the case folder. Then the grid is compared
+// with `grid.json` and the rendered frame with `frame.png`. Missing snapshots fail the case,
+// only `--update-snapshots` writes them.
+pub fn run(dir: &Path, update: bool, tolerance: u8) -> i32 {
+    let mut cases: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.join(SCRIPT).is_file())
+            .collect(),
+        Err(err) => {
+            eprintln!("Couldn't read {}: {err}", dir.display());
+            return 1;
+        }
+    };
+    cases.sort();
+
+    let mut failed = 0;
+    for case in &cases {
+        let name = case.file_name().unwrap_or_default().to_string_lossy();
+        match check(case, update, tolerance) {
+            Ok(Outcome::Passed) => println!("ok      {name}"),
+            Ok(Outcome::Written) => println!("written {name}"),
+            Ok(Outcome::Failed(problems)) => {
+                failed += 1;
+                println!("FAILED  {name}");
+                for problem in problems {
+                    println!("        {problem}");
+                }
+            }
+            Err(err) => {
+                failed += 1;
+                println!("ERROR   {name}: {err:#}");
+            }
+        }
+    }
+    println!("{} cases, {failed} failed", cases.len());
+    i32::from(failed > 0)
+}
+
+fn check(case: &Path, update: bool, tolerance: u8) -> Result<Outcome> {
+    let script = fs::read_to_string(case.join(SCRIPT)).context("Couldn't read script")?;
+    let config = Config::default();
+    let step = timestep::period(config.update_hz);
+    let mut game = Game::new(config);
+    game.set_directory(case);
+    for line in script.lines() {
+        game.apply_command(line);
+        game.update(step);
+    }
+    game.update(step);
+
+    let grid = game.grid();
+    let frame = raster::render(grid, game.font(), game.cell_size(), true);
+    let (grid_path, frame_path) = (case.join(GRID), case.join(FRAME));
+    if update {
+        fs::write(&grid_path, GridSnapshot::to_json(grid)?)?;
+        frame.save(&frame_path)?;
+        return Ok(Outcome::Written);
+    }
+    let missing: Vec<_> = [&grid_path, &frame_path]
+        .into_iter()
+        .filter(|path| !path.exists())
+        .map(|path| format!("{} is missing, run with --update-snapshots", path.display()))
+        .collect();
+    if !missing.is_empty() {
+        return Ok(Outcome::Failed(missing));
+    }
+
+    let mut problems = Vec::new();
+    let expected: GridSnapshot = serde_json::from_str(&fs::read_to_string(&grid_path)?)
+        .with_context(|| format!("Invalid {}", grid_path.display()))?;
+    problems.extend(grid_differences(&expected.into_grid(), grid));
+    let golden = image::open(&frame_path)
+        .with_context(|| format!("Couldn't open {}", frame_path.display()))?
+        .to_rgba8();
+    problems.extend(image_difference(&golden, &frame, tolerance));
+
+    Ok(if problems.is_empty() {
+        Outcome::Passed
+    } else {
+        Outcome::Failed(problems)
+    })
+}
+
+fn grid_differences(expected: &Grid, actual: &Grid) -> Option<String> {
+    if expected.size() != actual.size() {
+        return Some(format!(
+            "grid is {}x{}, expected {}x{}",
+            actual.width(),
+            actual.height(),
+            expected.width(),
+            expected.height()
+        ));
+    }
+    let differing: Vec<_> = (0..expected.height())
+        .flat_map(|y| (0..expected.width()).map(move |x| UVec2::new(x, y)))
+        .filter(|position| expected.get(*position) != actual.get(*position))
+        .collect();
+    let first = differing.first()?;
+    Some(format!(
+        "{} cells differ, first at {},{}: {:?}, expected {:?}",
+        differing.len(),
+        first.x,
+        first.y,
+        actual.get(*first),
+        expected.get(*first)
+    ))
+}
+
+// Pixels count as different when any channel is off by more than `tolerance`.
+fn image_difference(expected: &RgbaImage, actual: &RgbaImage, tolerance: u8) -> Option<String> {
+    if expected.dimensions() != actual.dimensions() {
+        return Some(format!(
+            "frame is {:?}, expected {:?}",
+            actual.dimensions(),
+            expected.dimensions()
+        ));
+    }
+    let differing = expected
+        .pixels()
+        .zip(actual.pixels())
+        .filter(|(a, b)| {
+            a.0.iter()
+                .zip(b.0.iter())
+                .any(|(a, b)| a.abs_diff(*b) > tolerance)
+        })
+        .count();
+    (differing > 0).then(|| format!("{differing} pixels differ from the golden frame"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_match() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots");
+        assert_eq!(run(&dir, false, 0), 0);
+    }
+
+    #[test]
+    fn missing_snapshots_fail_without_writing() {
+        let dir = std::env::temp_dir().join(format!("grid_renderer-{}", std::process::id()));
+        let case = dir.join("new-case");
+        fs::create_dir_all(&case).unwrap();
+        fs::write(case.join(SCRIPT), "w\n").unwrap();
+        let failed = run(&dir, false, 0);
+        let written = case.join(GRID).exists() || case.join(FRAME).exists();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(failed, 1);
+        assert!(!written);
+    }
+}
@@ -0,0 +1,106 @@
+use serde::Serialize;
+
+use std::time::{Duration, Instant};
+
+// Weight of the newest sample in the running averages.
+const SMOOTHING: f32 = 0.1;
+
+#[derive(Clone, Copy)]
+pub enum Phase {
+    Serve,
+    Input,
+    Update,
+    Draw,
+}
+
+// What `AppRequest::Stats` answers with, phase timings are averaged milliseconds.
+#[derive(Clone, Default, Serialize)]
+pub struct Stats {
+    pub fps: u32,
+    pub frame_ms: f32,
+    pub serve_ms: f32,
+    pub input_ms: f32,
+    pub update_ms: f32,
+    pub draw_ms: f32,
+    pub updates: u32,
+    pub tiles_drawn: usize,
+    pub pending_commands: usize,
+    // Connections accepted during the last second, clients reconnect for every request.
+    pub clients: u32,
+}
+
+pub struct FrameTimer {
+    stats: Stats,
+    frame_start: Instant,
+    second_start: Instant,
+    frames: u32,
+    connections: u32,
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        Self {
+            stats: Stats::default(),
+            frame_start: Instant::now(),
+            second_start: Instant::now(),
+            frames: 0,
+            connections: 0,
+        }
+    }
+
+    pub const fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn record(&mut self, phase: Phase, elapsed: Duration) {
+        let average = match phase {
+            Phase::Serve => &mut self.stats.serve_ms,
+            Phase::Input => &mut self.stats.input_ms,
+            Phase::Update => &mut self.stats.update_ms,
+            Phase::Draw => &mut self.stats.draw_ms,
+        };
+        smooth(average, elapsed);
+    }
+
+    pub const fn connection(&mut self) {
+        self.connections += 1;
+    }
+
+    pub fn frame(&mut self, updates: u32, tiles_drawn: usize, pending_commands: usize) {
+        let now = Instant::now();
+        smooth(&mut self.stats.frame_ms, now - self.frame_start);
+        self.frame_start = now;
+        self.stats.updates = updates;
+        self.stats.tiles_drawn = tiles_drawn;
+        self.stats.pending_commands = pending_commands;
+
+        self.frames += 1;
+        if now - self.second_start >= Duration::from_secs(1) {
+            self.stats.fps = self.frames;
+            self.stats.clients = self.connections;
+            self.frames = 0;
+            self.connections = 0;
+            self.second_start = now;
+        }
+    }
+}
+
+impl Stats {
+    pub fn lines(&self) -> Vec<String> {
+        vec![
+            format!("fps     {:>7} {:>6.2}ms", self.fps, self.frame_ms),
+            format!("serve   {:>6.2}ms", self.serve_ms),
+            format!("input   {:>6.2}ms", self.input_ms),
+            format!("update  {:>6.2}ms x{}", self.update_ms, self.updates),
+            format!("draw    {:>6.2}ms", self.draw_ms),
+            format!("tiles   {:>7}", self.tiles_drawn),
+            format!("pending {:>7}", self.pending_commands),
+            format!("clients {:>7}/s", self.clients),
+        ]
+    }
+}
+
+fn smooth(average: &mut f32, elapsed: Duration) {
+    let sample = elapsed.as_secs_f32() * 1000.0;
+    *average = (sample - *average).mul_add(SMOOTHING, *average);
+}
@@ -0,0 +1,138 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Runs game updates at a fixed rate no matter how often frames are drawn.
+pub struct Timestep {
+    step: Duration,
+    max_updates: u32,
+    accumulator: Duration,
+    last: Option<Instant>,
+}
+
+impl Timestep {
+    pub fn new(update_hz: u32, max_updates: u32) -> Self {
+        Self {
+            step: period(update_hz),
+            max_updates: max_updates.max(1),
+            accumulator: Duration::ZERO,
+            last: None,
+        }
+    }
+
+    pub const fn step(&self) -> Duration {
+        self.step
+    }
+
+    pub fn configure(&mut self, update_hz: u32, max_updates: u32) {
+        self.step = period(update_hz);
+        self.max_updates = max_updates.max(1);
+    }
+
+    // How many updates are due since the last call. After a long stall only `max_updates`
+    // run and the rest of the backlog is dropped instead of slowing every following frame.
+    pub fn advance(&mut self) -> u32 {
+        self.advance_to(Instant::now())
+    }
+
+    fn advance_to(&mut self, now: Instant) -> u32 {
+        let Some(last) = self.last.replace(now) else {
+            return 1;
+        };
+        self.accumulator += now - last;
+        let mut updates = 0;
+        while self.accumulator >= self.step && updates < self.max_updates {
+            self.accumulator -= self.step;
+            updates += 1;
+        }
+        if updates == self.max_updates {
+            self.accumulator = self.accumulator.min(self.step);
+        }
+        updates
+    }
+}
+
+// Sleeps away the rest of each frame to hold `target_fps`.
+pub struct Pacer {
+    frame: Option<Duration>,
+    vsync: bool,
+    deadline: Option<Instant>,
+}
+
+impl Pacer {
+    // With vsync the buffer swap already blocks, so a target of 0 never sleeps.
+    pub fn new(target_fps: u32, vsync: bool) -> Self {
+        Self {
+            frame: (target_fps > 0).then(|| period(target_fps)),
+            vsync,
+            deadline: None,
+        }
+    }
+
+    pub fn set_target(&mut self, target_fps: u32) {
+        self.frame = (target_fps > 0).then(|| period(target_fps));
+        self.deadline = None;
+    }
+
+    // Length of a frame at the target rate, `None` when uncapped.
+    pub const fn frame_duration(&self) -> Option<Duration> {
+        self.frame
+    }
+
+    pub fn wait(&mut self) {
+        let Some(frame) = self.frame else {
+            if !self.vsync {
+                thread::yield_now();
+            }
+            return;
+        };
+        let now = Instant::now();
+        let Some(previous) = self.deadline.replace(now) else {
+            return;
+        };
+        let deadline = previous + frame;
+        // A frame that ran long, or a swap that waited for the display, starts a new schedule
+        // rather than being made up with shorter frames.
+        if deadline <= now {
+            return;
+        }
+        thread::sleep(deadline - now);
+        self.deadline = Some(deadline);
+    }
+}
+
+pub fn period(hz: u32) -> Duration {
+    Duration::from_secs(1) / hz.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_call_runs_one_update() {
+        let mut timestep = Timestep::new(60, 5);
+        assert_eq!(timestep.advance_to(Instant::now()), 1);
+    }
+
+    #[test]
+    fn carries_the_remainder_to_the_next_frame() {
+        let mut timestep = Timestep::new(100, 5);
+        let start = Instant::now();
+        timestep.advance_to(start);
+        assert_eq!(timestep.advance_to(start + Duration::from_millis(25)), 2);
+        assert_eq!(timestep.advance_to(start + Duration::from_millis(30)), 1);
+        assert_eq!(timestep.advance_to(start + Duration::from_millis(34)), 0);
+    }
+
+    #[test]
+    fn drops_the_backlog_after_a_stall() {
+        let mut timestep = Timestep::new(100, 3);
+        let start = Instant::now();
+        timestep.advance_to(start);
+        assert_eq!(timestep.advance_to(start + Duration::from_secs(1)), 3);
+        // At most one step is kept, the rest of the second is gone.
+        let next = start + Duration::from_secs(1) + Duration::from_millis(5);
+        assert_eq!(timestep.advance_to(next), 1);
+        assert_eq!(timestep.advance_to(next + Duration::from_millis(10)), 1);
+    }
+}
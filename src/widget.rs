@@ -0,0 +1,525 @@
+use glam::UVec2;
+use serde::Serialize;
+use speedy2d::window::VirtualKeyCode;
+
+use crate::app::Keyboard;
+use crate::cp437::from_unicode;
+use crate::grid::Tile;
+use crate::layer::Layer;
+use crate::palette::Palette;
+
+// Oldest events are dropped when nobody collects them.
+const MAX_EVENTS: usize = 256;
+
+const TEXT: (u8, u8) = (15, 1);
+const HEADER: (u8, u8) = (14, 1);
+const FOCUSED: (u8, u8) = (0, 3);
+const FIELD: (u8, u8) = (15, 8);
+const BAR: (u8, u8) = (10, 1);
+
+const HORIZONTAL: char = '\u{c4}';
+const VERTICAL: char = '\u{b3}';
+const CORNERS: [char; 4] = ['\u{da}', '\u{bf}', '\u{c0}', '\u{d9}'];
+const FULL: char = '\u{db}';
+const EMPTY: char = '\u{b0}';
+
+pub const USAGE: &str = "Usage: ui label <id> <x,y> <text> | ui box <id> <x,y> <w,h> [title] | ui progress <id> <x,y> <width> <percent> | ui table <id> <x,y> <a|b;c|d> | ui list <id> <x,y> <a|b|c> | ui input <id> <x,y> <width> [text] | ui button <id> <x,y> <label> | ui set <id> <value> | ui focus|remove <id> | ui clear";
+
+#[derive(Clone, Debug)]
+pub enum Kind {
+    Label(String),
+    Box { size: UVec2, title: String },
+    // `value` goes from 0 to 100.
+    Progress { width: u32, value: u32 },
+    // The first row is the header.
+    Table(Vec<Vec<String>>),
+    List { items: Vec<String>, selected: usize },
+    Input { width: u32, text: String },
+    Button(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Widget {
+    pub id: String,
+    // Grid cell of the top left corner.
+    pub position: UVec2,
+    pub kind: Kind,
+}
+
+impl Widget {
+    const fn focusable(&self) -> bool {
+        matches!(
+            self.kind,
+            Kind::List { .. } | Kind::Input { .. } | Kind::Button(_)
+        )
+    }
+
+    fn column_widths(rows: &[Vec<String>]) -> Vec<u32> {
+        let mut widths = Vec::new();
+        for row in rows {
+            widths.resize(widths.len().max(row.len()), 0);
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count() as u32);
+            }
+        }
+        widths
+    }
+
+    pub fn size(&self) -> UVec2 {
+        let length = |text: &str| text.chars().count() as u32;
+        match &self.kind {
+            Kind::Label(text) => UVec2::new(length(text), 1),
+            Kind::Box { size, .. } => *size,
+            Kind::Progress { width, .. } | Kind::Input { width, .. } => UVec2::new(*width, 1),
+            Kind::Table(rows) => {
+                let widths = Self::column_widths(rows);
+                let separators = widths.len().saturating_sub(1) as u32;
+                UVec2::new(widths.iter().sum::<u32>() + separators, rows.len() as u32)
+            }
+            Kind::List { items, .. } => UVec2::new(
+                items.iter().map(|item| length(item)).max().unwrap_or(0),
+                items.len() as u32,
+            ),
+            Kind::Button(label) => UVec2::new(length(label) + 4, 1),
+        }
+    }
+
+    fn contains(&self, cell: UVec2) -> bool {
+        let end = self.position + self.size();
+        cell.cmpge(self.position).all() && cell.cmplt(end).all()
+    }
+
+    // Changes what the widget shows, the value means something different for every kind.
+    fn set(&mut self, value: &str) -> bool {
+        match &mut self.kind {
+            Kind::Label(text)
+            | Kind::Button(text)
+            | Kind::Box { title: text, .. }
+            | Kind::Input { text, .. } => *text = value.to_string(),
+            Kind::Progress { value: percent, .. } => match value.parse::<u32>() {
+                Ok(value) => *percent = value.min(100),
+                Err(_) => return false,
+            },
+            Kind::Table(rows) => *rows = parse_table(value),
+            Kind::List { items, selected } => match value.parse::<usize>() {
+                Ok(index) if index < items.len() => *selected = index,
+                _ => return false,
+            },
+        }
+        true
+    }
+
+    fn render(&self, layer: &mut Layer, palette: &Palette, focused: bool) {
+        let mut canvas = Canvas {
+            layer,
+            palette,
+            origin: self.position,
+        };
+        let size = self.size();
+        match &self.kind {
+            Kind::Label(label) => canvas.text(UVec2::ZERO, label, size.x, TEXT),
+            Kind::Box { title, .. } => {
+                if size.x < 2 || size.y < 2 {
+                    return;
+                }
+                let last = size - UVec2::ONE;
+                for y in 0..size.y {
+                    for x in 0..size.x {
+                        let ch = match (x, y) {
+                            (0, 0) => CORNERS[0],
+                            (x, 0) if x == last.x => CORNERS[1],
+                            (0, y) if y == last.y => CORNERS[2],
+                            (x, y) if x == last.x && y == last.y => CORNERS[3],
+                            (_, 0) => HORIZONTAL,
+                            (_, y) if y == last.y => HORIZONTAL,
+                            (0, _) => VERTICAL,
+                            (x, _) if x == last.x => VERTICAL,
+                            _ => ' ',
+                        };
+                        canvas.put(UVec2::new(x, y), ch, TEXT);
+                    }
+                }
+                let width = (title.chars().count() as u32).min(size.x.saturating_sub(4));
+                canvas.text(UVec2::new(2, 0), title, width, HEADER);
+            }
+            Kind::Progress { width, value } => {
+                let filled = width * value / 100;
+                for x in 0..*width {
+                    let ch = if x < filled { FULL } else { EMPTY };
+                    canvas.put(UVec2::new(x, 0), ch, BAR);
+                }
+            }
+            Kind::Table(rows) => {
+                let widths = Self::column_widths(rows);
+                for (y, row) in rows.iter().enumerate() {
+                    let colors = if y == 0 { HEADER } else { TEXT };
+                    let mut x = 0;
+                    for (column, width) in widths.iter().enumerate() {
+                        if column > 0 {
+                            canvas.put(UVec2::new(x, y as u32), VERTICAL, TEXT);
+                            x += 1;
+                        }
+                        let cell = row.get(column).map_or("", String::as_str);
+                        canvas.text(UVec2::new(x, y as u32), cell, *width, colors);
+                        x += width;
+                    }
+                }
+            }
+            Kind::List { items, selected } => {
+                for (y, item) in items.iter().enumerate() {
+                    let colors = match (y == *selected, focused) {
+                        (true, true) => FOCUSED,
+                        (true, false) => FIELD,
+                        _ => TEXT,
+                    };
+                    canvas.text(UVec2::new(0, y as u32), item, size.x, colors);
+                }
+            }
+            Kind::Input { width, text: value } => {
+                // The end of the text stays visible, with room for the caret.
+                let length = value.chars().count() as u32;
+                let shown = length.min(width.saturating_sub(1));
+                let value: String = value.chars().skip((length - shown) as usize).collect();
+                let colors = if focused { FOCUSED } else { FIELD };
+                canvas.text(UVec2::ZERO, &value, *width, colors);
+                if focused && shown < *width {
+                    canvas.put(UVec2::new(shown, 0), '_', colors);
+                }
+            }
+            Kind::Button(label) => {
+                let colors = if focused { FOCUSED } else { TEXT };
+                canvas.text(UVec2::ZERO, &format!("[ {label} ]"), size.x, colors);
+            }
+        }
+    }
+}
+
+struct Canvas<'a> {
+    layer: &'a mut Layer,
+    palette: &'a Palette,
+    origin: UVec2,
+}
+
+impl Canvas<'_> {
+    fn put(&mut self, offset: UVec2, ch: char, (fg, bg): (u8, u8)) {
+        let tile = Tile::new(ch)
+            .with_fg_index(fg, self.palette)
+            .with_bg_index(bg, self.palette);
+        self.layer.set(self.origin + offset, Some(tile));
+    }
+
+    // Padded with spaces or cut to `width`.
+    fn text(&mut self, offset: UVec2, text: &str, width: u32, colors: (u8, u8)) {
+        let chars = text.chars().map(from_unicode).chain(std::iter::repeat(' '));
+        for (x, ch) in (0..width).zip(chars) {
+            self.put(offset + UVec2::new(x, 0), ch, colors);
+        }
+    }
+}
+
+// What tools get back through `AppRequest::UiEvents`.
+#[derive(Clone, Debug, Serialize)]
+pub enum Event {
+    Clicked {
+        id: String,
+    },
+    Selected {
+        id: String,
+        index: usize,
+        item: String,
+    },
+    Submitted {
+        id: String,
+        text: String,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub enum UiChange {
+    Add(Widget),
+    Set(String, String),
+    Focus(String),
+    Remove(String),
+    Clear,
+}
+
+impl UiChange {
+    // Text arguments take the rest of the words. Positions and sizes are clamped to `bounds`,
+    // the size of the ui layer, so a typo can't make a widget millions of cells wide.
+    pub fn parse<'a>(args: &mut impl Iterator<Item = &'a str>, bounds: UVec2) -> Option<Self> {
+        let kind = args.next()?;
+        if kind == "clear" {
+            return Some(Self::Clear);
+        }
+        let id = args.next()?.to_string();
+        match kind {
+            "set" => return Some(Self::Set(id, rest(args))),
+            "focus" => return Some(Self::Focus(id)),
+            "remove" => return Some(Self::Remove(id)),
+            _ => (),
+        }
+        let position = parse_cell(args.next()?)?.min(bounds);
+        let width = |text: &str| text.parse::<u32>().ok().map(|width| width.min(bounds.x));
+        let kind = match kind {
+            "label" => Kind::Label(rest(args)),
+            "box" => Kind::Box {
+                size: parse_cell(args.next()?)?.min(bounds),
+                title: rest(args),
+            },
+            "progress" => Kind::Progress {
+                width: width(args.next()?)?,
+                value: args.next()?.parse::<u32>().ok()?.min(100),
+            },
+            "table" => Kind::Table(parse_table(&rest(args))),
+            "list" => Kind::List {
+                items: rest(args).split('|').map(str::to_string).collect(),
+                selected: 0,
+            },
+            "input" => Kind::Input {
+                width: width(args.next()?)?,
+                text: rest(args),
+            },
+            "button" => Kind::Button(rest(args)),
+            _ => return None,
+        };
+        Some(Self::Add(Widget { id, position, kind }))
+    }
+}
+
+// Widgets drawn into the `ui` layer, the focused one takes keyboard input.
+pub struct Ui {
+    widgets: Vec<Widget>,
+    focus: Option<usize>,
+    events: Vec<Event>,
+    changed: bool,
+    left_was_pressed: bool,
+}
+
+impl Ui {
+    pub const fn new() -> Self {
+        Self {
+            widgets: Vec::new(),
+            focus: None,
+            events: Vec::new(),
+            changed: false,
+            left_was_pressed: false,
+        }
+    }
+
+    pub const fn wants_keyboard(&self) -> bool {
+        self.focus.is_some()
+    }
+
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    // Returns false for an unknown id or a value the widget can't show.
+    pub fn apply(&mut self, change: UiChange) -> bool {
+        let index = |widgets: &[Widget], id: &str| widgets.iter().position(|w| w.id == id);
+        self.changed = true;
+        match change {
+            UiChange::Add(widget) => match index(&self.widgets, &widget.id) {
+                Some(idx) => self.widgets[idx] = widget,
+                None => self.widgets.push(widget),
+            },
+            UiChange::Set(id, value) => {
+                let Some(idx) = index(&self.widgets, &id) else {
+                    return false;
+                };
+                return self.widgets[idx].set(&value);
+            }
+            UiChange::Focus(id) => {
+                let Some(idx) = index(&self.widgets, &id) else {
+                    return false;
+                };
+                if !self.widgets[idx].focusable() {
+                    return false;
+                }
+                self.focus = Some(idx);
+            }
+            UiChange::Remove(id) => {
+                let Some(idx) = index(&self.widgets, &id) else {
+                    return false;
+                };
+                self.widgets.remove(idx);
+                self.focus = match self.focus {
+                    Some(focus) if focus == idx => None,
+                    Some(focus) if focus > idx => Some(focus - 1),
+                    focus => focus,
+                };
+            }
+            UiChange::Clear => {
+                self.widgets.clear();
+                self.focus = None;
+            }
+        }
+        true
+    }
+
+    // After a palette swap, or when something else cleared the layer.
+    pub const fn redraw(&mut self) {
+        self.changed = true;
+    }
+
+    // `cell` is the grid cell under the mouse.
+    pub fn input(&mut self, cell: Option<UVec2>, left: bool, keyboard: &Keyboard) {
+        let just_left = left && !self.left_was_pressed;
+        self.left_was_pressed = left;
+        if self.widgets.is_empty() {
+            return;
+        }
+        if just_left {
+            self.click(cell);
+        }
+        for key in &keyboard.just_pressed {
+            match key {
+                VirtualKeyCode::Tab => self.cycle_focus(keyboard.modifiers.shift()),
+                key => self.key(*key),
+            }
+            self.changed = true;
+        }
+        if let Some(Kind::Input { text, .. }) = self.focused_kind() {
+            let typed = keyboard.buffer.iter().filter(|ch| !ch.is_control());
+            let before = text.len();
+            text.extend(typed);
+            self.changed |= text.len() != before;
+        }
+    }
+
+    fn focused_kind(&mut self) -> Option<&mut Kind> {
+        self.focus
+            .and_then(|idx| self.widgets.get_mut(idx))
+            .map(|widget| &mut widget.kind)
+    }
+
+    fn click(&mut self, cell: Option<UVec2>) {
+        let hit = cell.and_then(|cell| {
+            let idx = self
+                .widgets
+                .iter()
+                .rposition(|w| w.focusable() && w.contains(cell))?;
+            Some((idx, cell - self.widgets[idx].position))
+        });
+        let previous = std::mem::replace(&mut self.focus, hit.map(|(idx, _)| idx));
+        self.changed |= previous != self.focus;
+        let Some((idx, offset)) = hit else {
+            return;
+        };
+        let widget = &mut self.widgets[idx];
+        let id = widget.id.clone();
+        match &mut widget.kind {
+            Kind::Button(_) => self.events.push(Event::Clicked { id }),
+            Kind::List { selected, .. } => {
+                *selected = offset.y as usize;
+                self.selected(idx);
+            }
+            _ => return,
+        }
+        self.changed = true;
+        self.trim_events();
+    }
+
+    fn cycle_focus(&mut self, backwards: bool) {
+        let focusable: Vec<usize> = (0..self.widgets.len())
+            .filter(|idx| self.widgets[*idx].focusable())
+            .collect();
+        let Some(&first) = focusable.first() else {
+            return;
+        };
+        let current = self
+            .focus
+            .and_then(|focus| focusable.iter().position(|idx| *idx == focus));
+        self.focus = Some(match (current, backwards) {
+            (None, false) => first,
+            (None, true) => focusable[focusable.len() - 1],
+            (Some(pos), false) => focusable[(pos + 1) % focusable.len()],
+            (Some(pos), true) => focusable[(pos + focusable.len() - 1) % focusable.len()],
+        });
+    }
+
+    fn key(&mut self, key: VirtualKeyCode) {
+        let Some(idx) = self.focus else {
+            return;
+        };
+        let submit = matches!(key, VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter);
+        let widget = &mut self.widgets[idx];
+        let id = widget.id.clone();
+        match &mut widget.kind {
+            Kind::Button(_) if submit || key == VirtualKeyCode::Space => {
+                self.events.push(Event::Clicked { id });
+            }
+            Kind::Input { text, .. } if key == VirtualKeyCode::Backspace => {
+                text.pop();
+            }
+            Kind::Input { text, .. } if submit => {
+                let text = text.clone();
+                self.events.push(Event::Submitted { id, text });
+            }
+            Kind::List { items, selected } => {
+                let previous = *selected;
+                match key {
+                    VirtualKeyCode::Up => *selected = selected.saturating_sub(1),
+                    VirtualKeyCode::Down => {
+                        *selected = (*selected + 1).min(items.len().saturating_sub(1));
+                    }
+                    _ if submit => {
+                        let text = items.get(*selected).cloned().unwrap_or_default();
+                        self.events.push(Event::Submitted { id, text });
+                    }
+                    _ => (),
+                }
+                if *selected != previous {
+                    self.selected(idx);
+                }
+            }
+            _ => (),
+        }
+        self.trim_events();
+    }
+
+    fn selected(&mut self, idx: usize) {
+        let widget = &self.widgets[idx];
+        if let Kind::List { items, selected } = &widget.kind {
+            self.events.push(Event::Selected {
+                id: widget.id.clone(),
+                index: *selected,
+                item: items.get(*selected).cloned().unwrap_or_default(),
+            });
+        }
+    }
+
+    fn trim_events(&mut self) {
+        let excess = self.events.len().saturating_sub(MAX_EVENTS);
+        self.events.drain(..excess);
+    }
+
+    // The layer belongs to the widgets once any were added, it is redrawn from scratch.
+    pub fn render(&mut self, layer: &mut Layer, palette: &Palette) {
+        if !self.changed {
+            return;
+        }
+        self.changed = false;
+        layer.clear();
+        for (idx, widget) in self.widgets.iter().enumerate() {
+            widget.render(layer, palette, self.focus == Some(idx));
+        }
+    }
+}
+
+fn rest<'a>(args: &mut impl Iterator<Item = &'a str>) -> String {
+    args.collect::<Vec<_>>().join(" ")
+}
+
+// `x,y`, also used for sizes.
+pub fn parse_cell(text: &str) -> Option<UVec2> {
+    let (x, y) = text.split_once(',')?;
+    Some(UVec2::new(x.parse().ok()?, y.parse().ok()?))
+}
+
+// Rows separated by `;`, cells by `|`.
+fn parse_table(text: &str) -> Vec<Vec<String>> {
+    text.split(';')
+        .map(|row| row.split('|').map(|cell| cell.trim().to_string()).collect())
+        .collect()
+}
@@ -0,0 +1,174 @@
+use anyhow::{bail, Context, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use glam::UVec2;
+use speedy2d::color::Color;
+
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::bytes::Reader;
+use crate::grid::{color_to_rgba, Grid, Tile};
+
+const VERSION: i32 = -1;
+const CELL_BYTES: usize = 10;
+// REXPaint marks empty cells of upper layers with a magenta background.
+const TRANSPARENT: [u8; 3] = [255, 0, 255];
+
+pub fn is_xp(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("xp"))
+}
+
+pub struct Layer {
+    pub width: u32,
+    pub height: u32,
+    // Column-major like the file, `None` for transparent cells.
+    pub cells: Vec<Option<Tile>>,
+}
+
+pub fn read_layers(bytes: &[u8]) -> Result<Vec<Layer>> {
+    let mut data = Vec::new();
+    GzDecoder::new(bytes)
+        .read_to_end(&mut data)
+        .context("Not a gzip compressed .xp file")?;
+    let mut reader = Reader::new(&data, 0);
+
+    let mut layer_count = reader.i32()?;
+    // Very old files have no version field and start with the layer count.
+    if layer_count < 0 {
+        layer_count = reader.i32()?;
+    }
+    if !(1..=9).contains(&layer_count) {
+        bail!("Unexpected layer count {layer_count}");
+    }
+
+    (0..layer_count)
+        .map(|_| {
+            let width = u32::try_from(reader.i32()?).context("Negative layer width")?;
+            let height = u32::try_from(reader.i32()?).context("Negative layer height")?;
+            let count = width
+                .checked_mul(height)
+                .with_context(|| format!("{width}x{height} layer is too big"))?;
+            let cells = (0..count)
+                .map(|_| {
+                    let glyph = reader.i32()?;
+                    let cell = reader.take(6)?;
+                    let (fg, bg) = (&cell[..3], &cell[3..]);
+                    if bg == TRANSPARENT {
+                        return Ok(None);
+                    }
+                    let ch = u32::try_from(glyph)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .unwrap_or(' ');
+                    Ok(Some(
+                        Tile::new(ch)
+                            .with_fg(Color::from_int_rgb(fg[0], fg[1], fg[2]))
+                            .with_bg(Color::from_int_rgb(bg[0], bg[1], bg[2])),
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Layer {
+                width,
+                height,
+                cells,
+            })
+        })
+        .collect()
+}
+
+pub fn import(bytes: &[u8]) -> Result<Grid> {
+    let layers = read_layers(bytes)?;
+    let width = layers.iter().map(|layer| layer.width).max().unwrap_or(1);
+    let height = layers.iter().map(|layer| layer.height).max().unwrap_or(1);
+    let mut grid = Grid::new(width.max(1), height.max(1));
+    for layer in &layers {
+        for (idx, cell) in layer.cells.iter().enumerate() {
+            if let Some(tile) = cell {
+                let idx = idx as u32;
+                grid.set(UVec2::new(idx / layer.height, idx % layer.height), *tile);
+            }
+        }
+    }
+    Ok(grid)
+}
+
+pub fn export(grid: &Grid) -> Result<Vec<u8>> {
+    let (width, height) = (grid.width(), grid.height());
+    let mut data = Vec::with_capacity(16 + (width * height) as usize * CELL_BYTES);
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    data.extend_from_slice(&1_i32.to_le_bytes());
+    data.extend_from_slice(&i32::try_from(width)?.to_le_bytes());
+    data.extend_from_slice(&i32::try_from(height)?.to_le_bytes());
+    for x in 0..width {
+        for y in 0..height {
+            let tile = grid.get(UVec2::new(x, y)).copied().unwrap_or_default();
+            data.extend_from_slice(&u32::from(tile.ch).to_le_bytes());
+            data.extend_from_slice(&color_to_rgba(tile.fg)[..3]);
+            data.extend_from_slice(&color_to_rgba(tile.bg)[..3]);
+        }
+    }
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&data)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: [u8; 3] = [0, 0, 0];
+
+    // Cells go column by column, as glyph and background.
+    fn layer(width: i32, height: i32, cells: &[(char, [u8; 3])]) -> Vec<u8> {
+        let mut data = width.to_le_bytes().to_vec();
+        data.extend_from_slice(&height.to_le_bytes());
+        for (glyph, bg) in cells {
+            data.extend_from_slice(&u32::from(*glyph).to_le_bytes());
+            data.extend_from_slice(&[255, 255, 255]);
+            data.extend_from_slice(bg);
+        }
+        data
+    }
+
+    fn xp(layers: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = VERSION.to_le_bytes().to_vec();
+        data.extend_from_slice(&i32::try_from(layers.len()).unwrap().to_le_bytes());
+        data.extend(layers.concat());
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut grid = Grid::new(3, 2);
+        let tile = Tile::new('x')
+            .with_fg(Color::from_int_rgb(255, 255, 85))
+            .with_bg(Color::from_int_rgb(0, 0, 170));
+        grid.set(UVec2::new(2, 0), tile);
+        let imported = import(&export(&grid).unwrap()).unwrap();
+        assert_eq!(imported.size(), grid.size());
+        assert_eq!(imported.get(UVec2::new(2, 0)), Some(&tile));
+    }
+
+    #[test]
+    fn upper_layers_are_transparent_where_magenta() {
+        let bytes = xp(&[
+            layer(2, 1, &[('a', BLACK), ('b', BLACK)]),
+            layer(2, 1, &[('c', TRANSPARENT), ('d', BLACK)]),
+        ]);
+        let grid = import(&bytes).unwrap();
+        assert_eq!(grid.get(UVec2::new(0, 0)).unwrap().ch, 'a');
+        assert_eq!(grid.get(UVec2::new(1, 0)).unwrap().ch, 'd');
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(import(b"not gzip").is_err());
+        assert!(import(&xp(&[])).is_err());
+        assert!(import(&xp(&[layer(-1, 1, &[])])).is_err());
+        assert!(import(&xp(&[layer(i32::MAX, i32::MAX, &[])])).is_err());
+        assert!(import(&xp(&[layer(2, 2, &[('a', BLACK)])])).is_err());
+    }
+}